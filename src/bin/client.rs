@@ -2,8 +2,8 @@ extern crate aqueren;
 extern crate hyper;
 extern crate rustc_serialize;
 
-use aqueren::server::{PlaceTileCmd};
-use aqueren::types::{Board, COLS, Game, Player, PlayerShares, Tile};
+use aqueren::server::{ActionCmd, ErrorResponse, EventBatch, GameEvent, JoinCmd, JoinResponse};
+use aqueren::types::{Action, BlockedPlacement, Board, FinalScore, GameConfig, GameView, Hotel, PlayerId, PlayerShares, PlayerView, StartingTile, Tile, TurnState, Unplayable};
 use hyper::client::Client;
 use hyper::client::response::Response;
 use rustc_serialize::json;
@@ -13,9 +13,11 @@ use std::env;
 use std::io;
 use std::io::Read;
 use std::io::Write;
+use std::thread;
+use std::time::Duration;
 
-fn dump_state(server_url: &str) {
-    match get_state(server_url) {
+fn dump_state(server_url: &str, seat: &JoinResponse) {
+    match get_state(server_url, &seat.token) {
         Ok(game) => {
             println!("{}", print_game(&game));
         },
@@ -23,42 +25,112 @@ fn dump_state(server_url: &str) {
     }
 }
 
-fn place_tile(server_url: &str, tile: Tile) {
-    let response = encode_place_tile(tile)
-        .and_then(|action| send_place_tile(server_url, action));
+fn act(server_url: &str, action: ActionCmd) {
+    let response = encode_action(&action)
+        .and_then(|body| send_action(server_url, body));
     match response {
         Ok(game) => println!("{}", print_game(&game)),
         Err(e) => println!("{:?}", e)
     }
 }
 
-fn encode_place_tile(tile: Tile) -> Result<String, String> {
-    json::encode(&PlaceTileCmd { tile: tile })
+fn encode_action(action: &ActionCmd) -> Result<String, String> {
+    json::encode(action)
         .map_err(|e| e.to_string())
 }
 
-fn send_place_tile(server_url: &str, action: String) -> Result<Game, String> {
+fn send_action(server_url: &str, action: String) -> Result<GameView, String> {
     Client::new()
         .post(&format!("{}/action", server_url))
         .body(action.as_bytes())
         .send()
-        .map_err(|e| format!("Error placing tile: {}", e.to_string()))
+        .map_err(|e| format!("Error sending action: {}", e.to_string()))
         .and_then(decode_response)
 }
 
-fn get_state(server_url: &str) -> Result<Game, String> {
+fn get_state(server_url: &str, token: &str) -> Result<GameView, String> {
     Client::new()
-        .get(&format!("{}/state", server_url))
+        .get(&format!("{}/state?token={}", server_url, token))
         .send()
         .map_err(|e| format!("Error getting state: {}", e.to_string()))
         .and_then(decode_response)
 }
 
+fn get_events(server_url: &str, token: &str, since: Option<usize>) -> Result<EventBatch, String> {
+    let cursor = since.map(|n| format!("&since={}", n)).unwrap_or(String::new());
+    Client::new()
+        .get(&format!("{}/events?token={}{}", server_url, token, cursor))
+        .send()
+        .map_err(|e| format!("Error getting events: {}", e.to_string()))
+        .and_then(decode_response)
+}
+
+/// Prints what the other players do as it happens. Runs until the client quits.
+fn watch_events(server_url: String, seat: JoinResponse) {
+    let mut since = None;
+    loop {
+        match get_events(&server_url, &seat.token, since) {
+            Ok(batch) => {
+                let others: Vec<&GameEvent> = batch.events.iter().filter(|e| e.player != seat.player).collect();
+                if !others.is_empty() {
+                    for event in others {
+                        println!("{}", print_event(event));
+                    }
+                    println!("Turn: player {}, {:?}", batch.turn, batch.turn_state);
+                }
+                since = Some(batch.next);
+            }
+            Err(e) => {
+                println!("{}", e);
+                thread::sleep(Duration::from_secs(5));
+            }
+        }
+    }
+}
+
+fn join(server_url: &str, name: String) -> Result<JoinResponse, String> {
+    json::encode(&JoinCmd { name: name })
+        .map_err(|e| e.to_string())
+        .and_then(|body| {
+            Client::new()
+                .post(&format!("{}/join", server_url))
+                .body(body.as_bytes())
+                .send()
+                .map_err(|e| format!("Error joining: {}", e.to_string()))
+        })
+        .and_then(decode_response)
+}
+
+/// Takes back a seat from its token, or joins in the next free seat without one.
+fn take_seat(server_url: &str, token: Option<String>) -> Result<JoinResponse, String> {
+    match token {
+        Some(token) => {
+            get_state(server_url, &token).and_then(|game| {
+                match game.viewer {
+                    Some(player) => Ok(JoinResponse { player: player, token: token }),
+                    None => Err("The server did not recognize the token".to_string())
+                }
+            })
+        }
+        None => {
+            print!("Your name: ");
+            let _ = io::stdout().flush();
+            read_input().and_then(|name| join(server_url, name))
+        }
+    }
+}
+
 fn decode_response<T: Decodable>(response: Response) -> Result<T, String> {
+    let success = response.status.is_success();
     parse_body(response)
         .and_then(|body| {
-            json::decode(&body)
-                  .map_err(|e| format!("Error parsing response '{}': {}", body, e))
+            if success {
+                json::decode(&body)
+                      .map_err(|e| format!("Error parsing response '{}': {}", body, e))
+            } else {
+                let error: Result<ErrorResponse, _> = json::decode(&body);
+                Err(error.map(|e| e.message).unwrap_or(body))
+            }
         })
 }
 
@@ -70,7 +142,46 @@ fn parse_body(mut response: Response) -> Result<String, String> {
     }
 }
 
-fn print_game(game: &Game) -> String {
+fn print_game(game: &GameView) -> String {
+    if game.turn_state == TurnState::WaitingForPlayers {
+        return print_lobby(game)
+    }
+    let mut string = print_status(game);
+    string.push_str(&format!("\n{}", print_starting_draw(&game.starting_draw)));
+    if let Some(ref blocked) = game.blocked_placement {
+        string.push_str(&format!("\n{}", print_blocked_placement(blocked, game.viewer)));
+    }
+    if let Some(ref score) = game.final_score {
+        string.push_str(&format!("\n{}", print_final_score(score)));
+    }
+    string
+}
+
+fn print_lobby(game: &GameView) -> String {
+    let players: Vec<String> = game.players
+        .iter()
+        .map(|p| {
+            let host = if game.host == Some(p.id) { " (host)" } else { "" };
+            let ready = if p.ready { "ready" } else { "not ready" };
+            format!("  Seat {}: {}{}, {}", p.id, p.name, host, ready)
+        })
+        .collect();
+    format!("Waiting for players ({} of {} seats taken)\n{}", game.players.len(), game.seats, players.join("\n"))
+}
+
+fn print_event(event: &GameEvent) -> String {
+    let what = match event.action {
+        Some(Action::Join { ref name, .. }) => format!("{} joined", name),
+        Some(Action::PlaceTile { ref tile, .. }) => format!("placed {}", print_tile(tile)),
+        Some(Action::FoundChain { ref hotel, .. }) => format!("founded {:?}", hotel),
+        Some(Action::ChooseSurvivor { ref hotel, .. }) => format!("chose {:?} to survive", hotel),
+        Some(Action::BuyStocks { ref hotels, .. }) => format!("bought {:?}", hotels),
+        _ => event.kind.replace("_", " ")
+    };
+    format!("Player {}: {}", event.player, what)
+}
+
+fn print_status(game: &GameView) -> String {
     format!("Game status\n\
              -------------------\
              \n\
@@ -78,47 +189,84 @@ fn print_game(game: &Game) -> String {
              \n\
              {board}\
              \n\
+             Chains: {chains}\
+             \n\
+             Bank: {bank}\
+             \n\
              Turn: Player {current_player:?} ({turn_state:?})",
             players=print_players(&game.players),
             board=print_board(&game.board),
+            chains=print_chains(&game.board.chain_sizes()),
+            bank=print_shares(&game.bank_shares),
             current_player=game.turn,
             turn_state=game.turn_state)
 }
 
-fn row_to_char<'a>(row: u8) -> &'a str {
-    let mapping = ["A", "B", "C", "D", "E", "F", "G", "H", "I"];
-    mapping[row as usize]
+fn print_starting_draw(starting_draw: &Vec<StartingTile>) -> String {
+    let draws: Vec<String> = starting_draw
+        .iter()
+        .map(|s| format!("Player {}: {}", s.player, print_tile(&s.tile)))
+        .collect();
+    format!("Starting draw (closest to 1A starts): {}", draws.join(", "))
 }
 
-fn char_to_row(row: char) -> Option<u8> {
-    let list = [
-        ('A', 0), ('a', 0),
-        ('B', 1), ('b', 1),
-        ('C', 2), ('c', 2),
-        ('D', 3), ('d', 3),
-        ('E', 4), ('e', 4),
-        ('F', 5), ('f', 5),
-        ('G', 6), ('g', 6),
-        ('H', 7), ('h', 7),
-        ('I', 8), ('i', 8)];
-    let mut mapping = HashMap::new();
-    for &(c, i) in list.iter() {
-        mapping.insert(c, i);
+fn print_blocked_placement(blocked: &BlockedPlacement, viewer: Option<PlayerId>) -> String {
+    if viewer != Some(blocked.player) {
+        return format!("Player {:?} cannot place any tile and must pass", blocked.player)
+    }
+    if blocked.tiles.is_empty() {
+        return format!("Player {:?} has no tiles and must pass", blocked.player)
     }
-    mapping.get(&row).map(|v| v.clone())
+    let tiles: Vec<String> = blocked.tiles
+        .iter()
+        .map(|t| {
+            let reason = match t.reason {
+                Unplayable::MergesSafeChains => "would merge safe chains",
+                Unplayable::NoChainAvailable => "no chain left to found"
+            };
+            format!("  {}: {}", print_tile(&t.tile), reason)
+        })
+        .collect();
+    format!("Player {:?} cannot place any tile and must pass\n{}", blocked.player, tiles.join("\n"))
+}
+
+fn print_final_score(score: &FinalScore) -> String {
+    let standings: Vec<String> = score.standings
+        .iter()
+        .map(|s| format!("  {}. Player {:?}: {}", s.rank, s.player, s.money))
+        .collect();
+    format!("Final score\n{}", standings.join("\n"))
+}
+
+fn row_to_char(row: u8) -> char {
+    (b'A' + row) as char
+}
+
+fn char_to_row(config: &GameConfig, row: char) -> Option<u8> {
+    let index = match row {
+        'A'...'Z' => row as u8 - b'A',
+        'a'...'z' => row as u8 - b'a',
+        _ => return None
+    };
+    if index < config.rows { Some(index) } else { None }
 }
 
-fn str_to_col(col_str: &str) -> Option<u8> {
+fn str_to_col(config: &GameConfig, col_str: &str) -> Option<u8> {
     match col_str.parse::<u8>() {
-        Ok(i) if i > 0 && i <= COLS => Some(i - 1),
+        Ok(i) if i > 0 && i <= config.cols => Some(i - 1),
         _ => None
     }
 }
 
+fn print_column_labels(cols: u8) -> String {
+    let labels: Vec<String> = (1..cols as u16 + 1).map(|c| format!("{:<2}", c)).collect();
+    format!("   {}\n", labels.join(" "))
+}
+
 fn print_board(board: &Board) -> String {
     let mut string = String::new();
-    string.push_str("   1  2  3  4  5  6  7  8  9  10 11 12\n");
-    for row in board.slots.chunks(COLS as usize) {
+    string.push_str(&print_column_labels(board.cols));
+    for row in board.slots.chunks(board.cols as usize) {
         let row_char = row_to_char(row[0].row);
         string.push_str(&format!("{}  ", row_char));
         for slot in row {
@@ -126,24 +274,29 @@ fn print_board(board: &Board) -> String {
         }
         string.push_str(&format!("{}\n", row_char));
     }
-    string.push_str("   1  2  3  4  5  6  7  8  9  10 11 12\n");
+    string.push_str(&print_column_labels(board.cols));
     string
 }
 
-fn print_players(players: &Vec<Player>) -> String {
+fn print_players(players: &Vec<PlayerView>) -> String {
     let players_str: Vec<String> = players.iter().map(print_player).collect();
     players_str.as_slice().join("\n")
 }
 
-fn print_player(player: &Player) -> String {
-    format!("Player {player:?}:\
+fn print_player(player: &PlayerView) -> String {
+    format!("Player {player:?} ({name}):\
              \n  Money: {money:?}\
              \n  Shares: {shares}\
              \n  Tiles: {tiles}",
             player=player.id,
+            name=player.name,
             money=player.money,
             shares=print_shares(&player.shares),
-            tiles=print_tiles(&player.tiles))
+            tiles=if player.tiles.is_empty() && player.hand_size > 0 {
+                format!("{} hidden", player.hand_size)
+            } else {
+                print_tiles(&player.tiles)
+            })
 }
 
 fn print_shares(shares: &PlayerShares) -> String {
@@ -153,6 +306,18 @@ fn print_shares(shares: &PlayerShares) -> String {
             shares.continental, shares.imperial)
 }
 
+fn print_chains(chains: &HashMap<Hotel, usize>) -> String {
+    if chains.is_empty() {
+        return "none".to_string()
+    }
+    let mut chains_str: Vec<String> = chains
+        .iter()
+        .map(|(hotel, size)| format!("{:?} ({})", hotel, size))
+        .collect();
+    chains_str.sort();
+    chains_str.join(", ")
+}
+
 fn print_tiles(tiles: &Vec<Tile>) -> String {
     let mut tiles_str: Vec<String> = tiles.iter().map(print_tile).collect();
     let mut slice = tiles_str.as_mut_slice();
@@ -165,62 +330,132 @@ fn print_tile(slot: &Tile) -> String {
     format!("{}{}", row_to_char(slot.row), slot.col + 1)
 }
 
-fn start_repl(server_url: &str) {
+fn start_repl(server_url: &str, seat: &JoinResponse, config: &GameConfig) {
     loop {
         print!("$ ");
         let _ = io::stdout().flush();
-        match read_command() {
-            Ok(cmd) => run_command(server_url, cmd),
+        match read_command(config, seat) {
+            Ok(cmd) => run_command(server_url, seat, cmd),
             Err(e) => println!("{}", e)
         }
     }
 }
 
-fn run_command(server_url: &str, command: Cmd) {
+fn run_command(server_url: &str, seat: &JoinResponse, command: Cmd) {
     match command {
-        Cmd::Dump => dump_state(server_url),
-        Cmd::Place { tile } => place_tile(server_url, tile)
+        Cmd::Dump => dump_state(server_url, seat),
+        Cmd::Act(action) => act(server_url, action)
     }
 }
 
 enum Cmd {
     Dump,
-    Place { tile: Tile }
+    Act(ActionCmd)
 }
 
-fn read_command() -> Result<Cmd, String> {
-    read_input().and_then(|s| parse_command(&s))
+fn read_command(config: &GameConfig, seat: &JoinResponse) -> Result<Cmd, String> {
+    read_input().and_then(|s| parse_command(config, seat, &s))
 }
 
-fn parse_command(string: &str) -> Result<Cmd, String> {
-    let mut parts = string.split(" ");
-    match parts.nth(0) {
-        Some("dump") => Ok(Cmd::Dump),
-        Some("place") => parse_place(string),
-        _ => Err(format!("'{}' is not a command, try 'dump'", string))
+fn parse_command(config: &GameConfig, seat: &JoinResponse, string: &str) -> Result<Cmd, String> {
+    let args: Vec<&str> = string.split_whitespace().collect();
+    let action = |name: &str| ActionCmd::new(name, seat.player, &seat.token);
+    match args.first() {
+        Some(&"dump") => Ok(Cmd::Dump),
+        Some(&"place") => {
+            parse_tile_arg(config, &args, "place B1")
+                .map(|tile| Cmd::Act(ActionCmd { tile: Some(tile), ..action("place_tile") }))
+        }
+        Some(&"replace") => {
+            parse_tile_arg(config, &args, "replace B1")
+                .map(|tile| Cmd::Act(ActionCmd { tile: Some(tile), ..action("replace_dead_tile") }))
+        }
+        Some(&"pass") => Ok(Cmd::Act(action("pass_placement"))),
+        Some(&"draw") => Ok(Cmd::Act(action("draw_tile"))),
+        Some(&"end") => Ok(Cmd::Act(action("end_game"))),
+        Some(&"ready") => Ok(Cmd::Act(ActionCmd { ready: Some(true), ..action("ready") })),
+        Some(&"unready") => Ok(Cmd::Act(ActionCmd { ready: Some(false), ..action("ready") })),
+        Some(&"start") => Ok(Cmd::Act(action("start_game"))),
+        Some(&"leave") => Ok(Cmd::Act(action("leave"))),
+        Some(&"found") => {
+            parse_hotel_arg(&args, "found Luxor")
+                .map(|hotel| Cmd::Act(ActionCmd { hotel: Some(hotel), ..action("found_chain") }))
+        }
+        Some(&"survivor") => {
+            parse_hotel_arg(&args, "survivor Luxor")
+                .map(|hotel| Cmd::Act(ActionCmd { hotel: Some(hotel), ..action("choose_survivor") }))
+        }
+        Some(&"defunct") => {
+            parse_hotel_arg(&args, "defunct Luxor")
+                .map(|hotel| Cmd::Act(ActionCmd { hotel: Some(hotel), ..action("choose_defunct") }))
+        }
+        Some(&"buy") => {
+            let hotels: Vec<Option<Hotel>> = args[1..].iter().map(|h| parse_hotel(h)).collect();
+            if hotels.iter().any(|h| h.is_none()) {
+                return Err("Couldn't parse hotels\nUsage example: buy Luxor Luxor Tower".to_string())
+            }
+            let hotels = hotels.into_iter().filter_map(|h| h).collect();
+            Ok(Cmd::Act(ActionCmd { hotels: Some(hotels), ..action("buy_stocks") }))
+        }
+        Some(&"merge") => {
+            let counts: Vec<u8> = args[1..].iter().filter_map(|c| c.parse::<u8>().ok()).collect();
+            if counts.len() != 3 || args.len() != 4 {
+                return Err("Give the number of shares to hold, sell and trade\nUsage example: merge 1 2 2".to_string())
+            }
+            Ok(Cmd::Act(ActionCmd {
+                hold: Some(counts[0]),
+                sell: Some(counts[1]),
+                trade: Some(counts[2]),
+                ..action("handle_merge_stocks")
+            }))
+        }
+        _ => Err(format!("'{}' is not a command, try dump, ready, unready, start, leave, place, replace, pass, found, survivor, defunct, merge, buy, draw or end", string))
     }
 }
 
-fn parse_place(string: &str) -> Result<Cmd, String> {
-    let example = "Usage example: place B1";
-    let mut parts = string.split(" ");
-    match parts.nth(1) {
+fn parse_tile_arg(config: &GameConfig, args: &Vec<&str>, example: &str) -> Result<Tile, String> {
+    match args.get(1) {
         Some(tile_str) => {
-            match parse_tile(tile_str) {
-                Some(tile) => Ok(Cmd::Place { tile: tile }),
-                None => Err(format!("Couldn't parse tile '{}'\n{}", tile_str, example))
+            match parse_tile(config, tile_str) {
+                Some(tile) => Ok(tile),
+                None => Err(format!("Couldn't parse tile '{}'\nUsage example: {}", tile_str, example))
             }
         }
-        None => Err(format!("Did you forget a tile?\n{}", example))
+        None => Err(format!("Did you forget a tile?\nUsage example: {}", example))
     }
 }
 
-fn parse_tile(string: &str) -> Option<Tile> {
+fn parse_hotel_arg(args: &Vec<&str>, example: &str) -> Result<Hotel, String> {
+    match args.get(1) {
+        Some(hotel_str) => {
+            match parse_hotel(hotel_str) {
+                Some(hotel) => Ok(hotel),
+                None => Err(format!("Couldn't parse hotel '{}'\nUsage example: {}", hotel_str, example))
+            }
+        }
+        None => Err(format!("Did you forget a hotel?\nUsage example: {}", example))
+    }
+}
+
+fn parse_hotel(string: &str) -> Option<Hotel> {
+    match string.to_lowercase().as_ref() {
+        "luxor" => Some(Hotel::Luxor),
+        "tower" => Some(Hotel::Tower),
+        "american" => Some(Hotel::American),
+        "festival" => Some(Hotel::Festival),
+        "worldwide" => Some(Hotel::Worldwide),
+        "continental" => Some(Hotel::Continental),
+        "imperial" => Some(Hotel::Imperial),
+        _ => None
+    }
+}
+
+fn parse_tile(config: &GameConfig, string: &str) -> Option<Tile> {
     let mut chars = string.chars();
     match (chars.nth(0), &string[1..string.len()]) {
         (Some(row_char), col_str) => {
-            match (char_to_row(row_char), str_to_col(col_str)) {
-                (Some(row), Some(col)) => Some(Tile { row: row, col: col }),
+            match (char_to_row(config, row_char), str_to_col(config, col_str)) {
+                (Some(row), Some(col)) => Tile::new(row, col, config),
                 _ => None
             }
         },
@@ -238,13 +473,24 @@ fn read_input() -> Result<String, String> {
 }
 
 fn main() {
-    let server_url = env::args().nth(1).unwrap_or("http://localhost:3001".to_string());
-    println!("Starting client, connecting to {}", server_url);
-    match get_state(&server_url) {
+    let base_url = env::args().nth(1).unwrap_or("http://localhost:3001".to_string());
+    let game_id = env::args().nth(2).unwrap_or("1".to_string());
+    let server_url = format!("{}/games/{}", base_url, game_id);
+    println!("Starting client, connecting to game {} on {}", game_id, base_url);
+    let seat = match take_seat(&server_url, env::args().nth(3)) {
+        Ok(seat) => seat,
+        Err(e) => panic!("{}", e)
+    };
+    println!("Playing as player {}, rejoin with token {}", seat.player, seat.token);
+    let config = match get_state(&server_url, &seat.token) {
         Ok(game) => {
             println!("\n{}", print_game(&game));
+            game.config
         },
         Err(e) => panic!("{}", e)
-    }
-    start_repl(&server_url);
+    };
+    let watched_url = server_url.clone();
+    let watching = JoinResponse { player: seat.player, token: seat.token.clone() };
+    thread::spawn(move || watch_events(watched_url, watching));
+    start_repl(&server_url, &seat, &config);
 }
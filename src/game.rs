@@ -1,85 +1,179 @@
 extern crate rand;
 
 use types::*;
-use std::collections::HashSet;
+use self::rand::{Isaac64Rng, Rng, SeedableRng};
+use std::collections::HashMap;
 
 pub fn new_actions() -> Vec<Action> {
     let actions: Vec<Action> = Vec::new();
     actions
 }
 
-fn all_tiles() -> Vec<Tile> {
-    (0..TILES).map(|i| {
-        let row = i / COLS;
-        let col = i % ROWS;
-        if let Some(tile) = Tile::new(row, col) {
-            tile
-        } else {
-            panic!("Attempted to create invalid tile ({},{})", row, col)
-        }
+fn all_tiles(config: &GameConfig) -> Vec<Tile> {
+    (0..config.rows).flat_map(|row| -> Vec<Tile> {
+        (0..config.cols).map(|col| {
+            if let Some(tile) = Tile::new(row, col, config) {
+                tile
+            } else {
+                panic!("Attempted to create invalid tile ({},{})", row, col)
+            }
         }).collect()
+    }).collect()
 }
 
-pub fn new_game() -> Game {
-    let (starting_tiles, remaining_tiles) = choose_tiles(all_tiles(), PLAYERS);
-    let players = new_players(remaining_tiles);
-    let slots = initial_slots(starting_tiles);
-    Game {
-        board: Board { slots: slots },
-        players: players,
-        turn: PlayerId::One,
-        turn_state: TurnState::Placing
+pub fn new_game(config: GameConfig, player_count: u8) -> Result<Game, GameError> {
+    new_game_with_seed(config, player_count, rand::random())
+}
+
+/// The seed fixes the order of the tile bag, so the same seed and actions always give the same game.
+/// Every seat is taken and the tiles are dealt, so the game begins right away.
+pub fn new_game_with_seed(config: GameConfig, player_count: u8, seed: u64) -> Result<Game, GameError> {
+    new_lobby(config, player_count, seed).map(|lobby| {
+        let players = (1..player_count + 1).map(|id| new_player(&lobby.config, id, Vec::new())).collect();
+        deal(Game { players: players, host: Some(1), ..lobby })
+    })
+}
+
+/// A game with `seats` empty seats, waiting for players to join and for the host to start it.
+pub fn new_lobby(config: GameConfig, seats: u8, seed: u64) -> Result<Game, GameError> {
+    if seats < MIN_PLAYERS || seats > MAX_PLAYERS {
+        return Err(GameError::InvalidPlayerCount(seats))
+    }
+    let tiles_needed = seats as usize * (config.hand_size as usize + 1);
+    let tile_count = config.rows as usize * config.cols as usize;
+    if config.rows == 0 || config.cols == 0 || config.rows > MAX_ROWS || tile_count < tiles_needed {
+        return Err(GameError::InvalidBoardSize { rows: config.rows, cols: config.cols })
     }
+    if let Some(reason) = config_out_of_range(&config) {
+        return Err(GameError::InvalidConfig(reason))
+    }
+    Ok(Game {
+        bank_shares: full_bank(&config),
+        board: initial_board(&config, &Vec::new()),
+        draw_pile: shuffled_tiles(&config, seed),
+        config: config,
+        seed: seed,
+        seats: seats,
+        host: None,
+        players: Vec::new(),
+        starting_draw: Vec::new(),
+        turn: 0,
+        turn_state: TurnState::WaitingForPlayers,
+        placed_tile: None,
+        blocked_placement: None,
+        merger: None,
+        final_score: None
+    })
 }
 
-fn choose_tiles(tiles: Vec<Tile>, count: u8) -> (Vec<Tile>, Vec<Tile>) {
-    let mut remaining_tiles = tiles;
-    let mut random_tiles = Vec::new();
-    for _ in 0..count {
-        let random_index = rand::random::<usize>() % remaining_tiles.len();
-        random_tiles.push(remaining_tiles.remove(random_index));
+/// Why the money in `config` is out of range, if it is. Configs come from clients, and prices
+/// or bonuses that large would overflow while the game is played.
+fn config_out_of_range(config: &GameConfig) -> Option<String> {
+    let prices = [
+        ("cheap_price", config.cheap_price),
+        ("medium_price", config.medium_price),
+        ("expensive_price", config.expensive_price),
+        ("price_step", config.price_step)
+    ];
+    let bonuses = [("majority_bonus", config.majority_bonus), ("minority_bonus", config.minority_bonus)];
+    if config.starting_cash < 0 || config.starting_cash > MAX_STARTING_CASH {
+        return Some(format!("starting_cash must be between 0 and {}", MAX_STARTING_CASH))
+    }
+    if let Some(&(name, _)) = prices.iter().find(|&&(_, price)| price < 0 || price > MAX_PRICE) {
+        return Some(format!("{} must be between 0 and {}", name, MAX_PRICE))
+    }
+    if let Some(&(name, _)) = bonuses.iter().find(|&&(_, bonus)| bonus < 0 || bonus > MAX_BONUS) {
+        return Some(format!("{} must be between 0 and {}", name, MAX_BONUS))
     }
-    (random_tiles, remaining_tiles)
+    if config.price_levels.len() > MAX_PRICE_LEVELS {
+        return Some(format!("price_levels may have at most {} levels", MAX_PRICE_LEVELS))
+    }
+    None
 }
 
-fn new_players(tiles: Vec<Tile>) -> Vec<Player>{
+/// Draws a starting tile for every seated player, deals their hands and gives the turn to
+/// whoever drew closest to 1A.
+fn deal(game: Game) -> Game {
+    let (starting_tiles, remaining_tiles) = take_tiles(game.draw_pile.clone(), game.players.len() as u8);
+    let starting_draw: Vec<StartingTile> = game.players
+        .iter()
+        .zip(starting_tiles.iter())
+        .map(|(p, tile)| StartingTile { player: p.id, tile: tile.clone() })
+        .collect();
+    let first_player = starting_player(&starting_draw);
+    let (players, draw_pile) = deal_hands(&game.config, &game.players, remaining_tiles);
+    let board = initial_board(&game.config, &starting_tiles);
+    begin_turn(Game {
+        board: board,
+        players: players,
+        draw_pile: draw_pile,
+        starting_draw: starting_draw,
+        turn: first_player,
+        ..game
+    })
+}
+
+/// The player who drew the tile closest to 1A starts. Columns count before rows, so 1I beats 2A.
+fn starting_player(starting_draw: &Vec<StartingTile>) -> PlayerId {
+    starting_draw
+        .iter()
+        .min_by_key(|s| (s.tile.col(), s.tile.row()))
+        .map_or(1, |s| s.player)
+}
+
+fn shuffled_tiles(config: &GameConfig, seed: u64) -> Vec<Tile> {
+    let mut rng: Isaac64Rng = SeedableRng::from_seed(&[seed][..]);
+    let mut tiles = all_tiles(config);
+    rng.shuffle(&mut tiles);
+    tiles
+}
+
+/// Takes `count` tiles from the top of the pile, which is its end.
+fn take_tiles(tiles: Vec<Tile>, count: u8) -> (Vec<Tile>, Vec<Tile>) {
+    let mut remaining_tiles = tiles;
+    let split_at = remaining_tiles.len().saturating_sub(count as usize);
+    let taken_tiles = remaining_tiles.split_off(split_at);
+    (taken_tiles, remaining_tiles)
+}
+
+fn deal_hands(config: &GameConfig, players: &Vec<Player>, tiles: Vec<Tile>) -> (Vec<Player>, Vec<Tile>) {
     let init_players: Vec<Player> = Vec::new();
-    let (players, _) = (0..PLAYERS)
-        .fold( (init_players, tiles), | (mut v, remaining), i | {
-            let (player_tiles, new_remaining) = choose_tiles(remaining, 6);
-            let player = new_player(PlayerId::new(i+1).unwrap(), player_tiles);
-            v.push(player);
+    players.iter()
+        .fold( (init_players, tiles), | (mut v, remaining), player | {
+            let (player_tiles, new_remaining) = take_tiles(remaining, config.hand_size);
+            v.push(Player { tiles: player_tiles, ..player.clone() });
             (v, new_remaining)
-        });
-    players
+        })
 }
 
-pub fn new_player(id: PlayerId, tiles: Vec<Tile>) -> Player {
-    Player { id: id, money: 6000, shares: empty_shares(), tiles: tiles }
+pub fn new_player(config: &GameConfig, id: PlayerId, tiles: Vec<Tile>) -> Player {
+    Player {
+        id: id,
+        name: format!("Player {}", id),
+        ready: false,
+        money: config.starting_cash,
+        shares: empty_shares(),
+        tiles: tiles
+    }
 }
 
 fn empty_shares() -> PlayerShares {
     PlayerShares { luxor: 0, tower: 0, american: 0, festival: 0, worldwide: 0, continental: 0, imperial: 0 }
 }
 
-pub fn initial_slots(starting_tiles: Vec<Tile>) -> Vec<Slot> {
-    (0..ROWS).flat_map(|row| -> Vec<Slot> {
-        (0..COLS).map(|col| {
-            Slot {
-                row: row,
-                col: col,
-                hotel: None,
-                has_tile: has_tile_on_slot(&starting_tiles, row, col)
-            }
-        }).collect()
-    }).collect()
+pub fn full_bank(config: &GameConfig) -> PlayerShares {
+    all_hotels().into_iter().fold(empty_shares(), |shares, h| set_shares(shares, h, config.shares_per_hotel))
 }
 
-fn has_tile_on_slot(tiles: & Vec<Tile>, row: u8, col: u8) -> bool {
-    tiles.iter().any(|t| t.row() == row && t.col() == col)
+pub fn initial_board(config: &GameConfig, starting_tiles: &Vec<Tile>) -> Board {
+    let mut board = Board::new(config.rows, config.cols);
+    for tile in starting_tiles.iter() {
+        board.place(tile);
+    }
+    board
 }
 
-pub fn compute_state(last_state: &Game, actions: &Vec<Action>) -> Result<Game, String> {
+pub fn compute_state(last_state: &Game, actions: &Vec<Action>) -> Result<Game, GameError> {
     actions.iter().fold(Ok(last_state.clone()), |last_result, action| {
         match last_result {
             Ok(game) => { play_turn(&game, action) }
@@ -88,117 +182,421 @@ pub fn compute_state(last_state: &Game, actions: &Vec<Action>) -> Result<Game, S
     })
 }
 
-pub fn play_turn(game: &Game, action: &Action) -> Result<Game, String> {
+pub fn play_turn(game: &Game, action: &Action) -> Result<Game, GameError> {
+    let player = action_player(action);
+    let joining = match *action { Action::Join { .. } => true, _ => false };
+    if !joining && !game.players.iter().any(|p| p.id == player) {
+        return Err(GameError::UnknownPlayer(player))
+    }
     match *action {
-        Action::DrawTile => {
-            draw_tile(game)
+        Action::Join { player, ref name } => {
+            join(game, player, name)
+        }
+        Action::Leave { player } => {
+            leave(game, player)
+        }
+        Action::Ready { player, ready } => {
+            set_ready(game, player, ready)
+        }
+        Action::StartGame { player } => {
+            start_game(game, player)
+        }
+        Action::DrawTile { player, ref tile } => {
+            draw_tile(game, player, tile)
+        }
+        Action::EndTurn { player } => {
+            end_turn(game, player)
+        }
+        Action::PlaceTile { player, ref tile } => {
+            place_tile(game, player, tile)
+        }
+        Action::PassPlacement { player } => {
+            pass_placement(game, player)
+        }
+        Action::ReplaceDeadTile { player, ref tile, ref replacement } => {
+            replace_dead_tile(game, player, tile, replacement)
+        }
+        Action::BuyStocks { player, ref hotels } => {
+            buy_stocks(game, player, hotels)
+        }
+        Action::FoundChain { player, ref hotel } => {
+            found_chain(game, player, hotel.clone())
+        }
+        Action::ChooseSurvivor { player, ref hotel } => {
+            choose_survivor(game, player, hotel.clone())
         }
-        Action::PlaceTile { ref player, ref tile } => {
-            place_tile(game, player.clone(), tile)
+        Action::ChooseDefunct { player, ref hotel } => {
+            choose_defunct(game, player, hotel.clone())
         }
-        Action::BuyStocks { ref player, ref hotel1, ref hotel2, ref hotel3 } => {
-            buy_stocks(game, player.clone(), hotel1.clone(), hotel2.clone(), hotel3.clone())
+        Action::HandleMergeStocks { player, hold, sell, trade } => {
+            handle_merge_stocks(game, player, hold, sell, trade)
+        }
+        Action::EndGame { player } => {
+            end_game(game, player)
         }
-        _ => panic!(format!("I don't know how to play a turn with action {:?}", action))
     }
 }
 
-fn draw_tile(game: &Game) -> Result<Game, String> {
-    if game.turn_state != TurnState::Drawing && game.turn_state != TurnState::BuyingOrDrawing {
-        let error_msg = format!("Error drawing tile: player {:?} is not allowed to draw a tile", game.turn);
-        return Err(error_msg)
+/// Resolves a draw to the concrete tile on top of the bag, so the recorded action replays
+/// to the same hand no matter how the bag is stored. Once the bag is empty the draw is skipped
+/// and the turn just ends.
+pub fn draw_action(game: &Game, player: PlayerId) -> Result<Action, GameError> {
+    match game.draw_pile.last() {
+        Some(tile) => Ok(Action::DrawTile { player: player, tile: tile.clone() }),
+        None => Ok(Action::EndTurn { player: player })
+    }
+}
+
+pub fn action_player(action: &Action) -> PlayerId {
+    match *action {
+        Action::Join { player, .. } => player,
+        Action::Leave { player } => player,
+        Action::Ready { player, .. } => player,
+        Action::StartGame { player } => player,
+        Action::PlaceTile { player, .. } => player,
+        Action::PassPlacement { player } => player,
+        Action::ChooseSurvivor { player, .. } => player,
+        Action::ChooseDefunct { player, .. } => player,
+        Action::HandleMergeStocks { player, .. } => player,
+        Action::BuyStocks { player, .. } => player,
+        Action::FoundChain { player, .. } => player,
+        Action::DrawTile { player, .. } => player,
+        Action::EndTurn { player } => player,
+        Action::ReplaceDeadTile { player, .. } => player,
+        Action::EndGame { player } => player
+    }
+}
+
+/// Resolves a join to the first free seat.
+pub fn join_action(game: &Game, name: &str) -> Result<Action, GameError> {
+    match (1..game.seats + 1).find(|&seat| !game.players.iter().any(|p| p.id == seat)) {
+        Some(seat) => Ok(Action::Join { player: seat, name: name.to_string() }),
+        None => Err(GameError::GameFull)
+    }
+}
+
+/// Seats a new player. The first player to join becomes the host.
+fn join(game: &Game, seat: PlayerId, name: &str) -> Result<Game, GameError> {
+    if game.turn_state != TurnState::WaitingForPlayers {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    if seat < 1 || seat > game.seats {
+        return Err(GameError::UnknownPlayer(seat))
+    }
+    if game.players.iter().any(|p| p.id == seat) {
+        return Err(GameError::SeatTaken(seat))
+    }
+    let player = Player { name: name.to_string(), ..new_player(&game.config, seat, Vec::new()) };
+    let mut players = game.players.clone();
+    let index = players.iter().position(|p| p.id > seat).unwrap_or(players.len());
+    players.insert(index, player);
+    Ok(Game {
+        players: players,
+        host: game.host.or(Some(seat)),
+        ..game.clone()
+    })
+}
+
+/// Frees the player's seat. A leaving host hands over to the player in the lowest seat.
+fn leave(game: &Game, player_id: PlayerId) -> Result<Game, GameError> {
+    if game.turn_state != TurnState::WaitingForPlayers {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    let players: Vec<Player> = game.players.iter().filter(|p| p.id != player_id).cloned().collect();
+    let host = if game.host == Some(player_id) { players.first().map(|p| p.id) } else { game.host };
+    Ok(Game {
+        players: players,
+        host: host,
+        ..game.clone()
+    })
+}
+
+fn set_ready(game: &Game, player_id: PlayerId, ready: bool) -> Result<Game, GameError> {
+    if game.turn_state != TurnState::WaitingForPlayers {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
     }
-    let remaining = get_remaining_tiles(&game);
-    let (mut tiles, _) = choose_tiles(remaining, 1);
-    let drawn_tile = tiles.pop().unwrap();
-    let new_players = add_tile_to_player(game.players.clone(), game.turn.clone(), &drawn_tile);
+    let players = game.players
+        .iter()
+        .map(|p| if p.id == player_id { Player { ready: ready, ..p.clone() } } else { p.clone() })
+        .collect();
     Ok(Game {
+        players: players,
+        ..game.clone()
+    })
+}
+
+/// The host may start once enough players have joined and all of them are ready.
+fn start_game(game: &Game, player_id: PlayerId) -> Result<Game, GameError> {
+    if game.turn_state != TurnState::WaitingForPlayers {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    if game.host != Some(player_id) {
+        return Err(GameError::NotHost(player_id))
+    }
+    let joined = game.players.len() as u8;
+    if joined < MIN_PLAYERS {
+        return Err(GameError::NotEnoughPlayers(joined))
+    }
+    if !game.players.iter().all(|p| p.ready) {
+        return Err(GameError::PlayersNotReady)
+    }
+    Ok(deal(game.clone()))
+}
+
+/// What `viewer` is allowed to see of the game. Pass None for a spectator.
+pub fn game_view(game: &Game, viewer: Option<PlayerId>) -> GameView {
+    let players = game.players
+        .iter()
+        .map(|p| PlayerView {
+            id: p.id,
+            name: p.name.clone(),
+            ready: p.ready,
+            money: p.money,
+            shares: p.shares.clone(),
+            hand_size: p.tiles.len(),
+            tiles: if viewer == Some(p.id) { p.tiles.clone() } else { Vec::new() }
+        })
+        .collect();
+    GameView {
+        viewer: viewer,
+        config: game.config.clone(),
+        seats: game.seats,
+        host: game.host,
+        players: players,
         board: game.board.clone(),
+        tiles_in_bag: game.draw_pile.len(),
+        bank_shares: game.bank_shares.clone(),
+        starting_draw: game.starting_draw.clone(),
+        turn: game.turn,
+        turn_state: game.turn_state.clone(),
+        placed_tile: game.placed_tile.clone(),
+        blocked_placement: game.blocked_placement.as_ref().map(|blocked| BlockedPlacement {
+            player: blocked.player,
+            tiles: if viewer == Some(blocked.player) { blocked.tiles.clone() } else { Vec::new() }
+        }),
+        merger: game.merger.clone(),
+        final_score: game.final_score.clone()
+    }
+}
+
+/// What `viewer` is allowed to see of an action. Drawn tiles go to the drawing player's hand,
+/// so only they see the actions that draw one.
+pub fn action_view(action: &Action, viewer: Option<PlayerId>) -> Option<Action> {
+    match *action {
+        Action::DrawTile { player, .. } | Action::ReplaceDeadTile { player, .. } if viewer != Some(player) => None,
+        _ => Some(action.clone())
+    }
+}
+
+fn draw_tile(game: &Game, player_id: PlayerId, tile: &Tile) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    if game.turn_state != TurnState::Drawing && game.turn_state != TurnState::BuyingOrDrawing {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    let draw_pile = match take_from_bag(&game.draw_pile, tile) {
+        Ok(draw_pile) => draw_pile,
+        Err(e) => return Err(e)
+    };
+    let new_players = add_tile_to_player(game.players.clone(), player_id, tile);
+    Ok(begin_turn(Game {
         players: new_players,
-        turn: next_turn(game.turn.clone()),
-        turn_state: TurnState::Placing
+        draw_pile: draw_pile,
+        turn: next_turn(game, game.turn),
+        ..game.clone()
+    }))
+}
+
+/// Passes the turn on without a draw. Only allowed when the bag is empty.
+fn end_turn(game: &Game, player_id: PlayerId) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    if game.turn_state != TurnState::Drawing && game.turn_state != TurnState::BuyingOrDrawing {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    if !game.draw_pile.is_empty() {
+        return Err(GameError::MustDrawTile)
+    }
+    Ok(begin_turn(Game {
+        turn: next_turn(game, game.turn),
+        ..game.clone()
+    }))
+}
+
+/// Puts the player whose turn it is into placing, noting if none of their tiles can be placed.
+fn begin_turn(game: Game) -> Game {
+    let blocked = blocked_placement(&game, game.turn);
+    Game {
+        turn_state: TurnState::Placing,
+        placed_tile: None,
+        blocked_placement: blocked,
+        ..game
+    }
+}
+
+pub fn blocked_placement(game: &Game, player_id: PlayerId) -> Option<BlockedPlacement> {
+    let tiles = game.players.iter().find(|p| p.id == player_id).map_or(Vec::new(), |p| p.tiles.clone());
+    let blocked: Vec<BlockedTile> = tiles
+        .iter()
+        .filter_map(|t| unplayable_reason(game, t).map(|reason| BlockedTile { tile: t.clone(), reason: reason }))
+        .collect();
+    if blocked.len() == tiles.len() {
+        Some(BlockedPlacement { player: player_id, tiles: blocked })
+    } else {
+        None
+    }
+}
+
+fn pass_placement(game: &Game, player_id: PlayerId) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    if game.turn_state != TurnState::Placing {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    if blocked_placement(game, player_id).is_none() {
+        return Err(GameError::MustPlaceTile)
+    }
+    Ok(Game {
+        turn_state: TurnState::BuyingOrDrawing,
+        blocked_placement: None,
+        ..game.clone()
     })
 }
 
-fn get_remaining_tiles(game: &Game) -> Vec<Tile> {
-    let mut remaining_tiles: HashSet<Tile> = all_tiles().iter().cloned().collect();
-    for player in game.players.iter() {
-        for tile in player.tiles.iter() {
-            remaining_tiles.remove(&tile);
-        }
+/// Tiles are only ever drawn from the top of the bag, so a recorded draw must name that tile.
+fn take_from_bag(draw_pile: &Vec<Tile>, tile: &Tile) -> Result<Vec<Tile>, GameError> {
+    if draw_pile.last() != Some(tile) {
+        return Err(GameError::TileNotInBag(tile.clone()))
     }
-    let board_tiles = game.board.slots.iter().filter(|s| s.has_tile).map(|s| Tile{ row: s.row, col: s.col });
-    for tile in board_tiles {
-        remaining_tiles.remove(&tile);
+    let mut remaining = draw_pile.clone();
+    remaining.pop();
+    Ok(remaining)
+}
+
+fn next_turn(game: &Game, player_id: PlayerId) -> PlayerId {
+    let index = game.players.iter().position(|p| p.id == player_id).unwrap_or(0);
+    game.players[(index + 1) % game.players.len()].id
+}
+
+fn place_tile(game: &Game, player_id: PlayerId, tile: &Tile) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    if game.turn_state != TurnState::Placing {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    if !game_player_has_tile(game, player_id, tile) {
+        return Err(GameError::TileNotInHand(player_id, tile.clone()))
+    }
+    if let Some(reason) = unplayable_reason(game, tile) {
+        return Err(GameError::IllegalTile(tile.clone(), reason))
+    }
+    let adjacent_hotels = adjacent_hotels(&game.board, &tile);
+    let mut game_after = Game {
+        players: remove_tile_from_player(game.players.clone(), player_id, tile),
+        turn_state: state_after_place_tile(&game.board, &tile),
+        placed_tile: Some(tile.clone()),
+        ..game.clone()
+    };
+    game_after.board.place(tile);
+    if adjacent_hotels.len() == 1 {
+        let group = game_after.board.connected_group(tile);
+        game_after.board.tag(&group, &adjacent_hotels[0]);
+    }
+    if game_after.turn_state == TurnState::Merging {
+        Ok(start_merger(&game_after, adjacent_hotels))
+    } else {
+        Ok(game_after)
     }
-    remaining_tiles.iter().cloned().collect()
 }
 
-fn next_turn(player_id: PlayerId) -> PlayerId {
-    match player_id {
-        PlayerId::One => { PlayerId::Two }
-        PlayerId::Two => { PlayerId::Three }
-        PlayerId::Three => { PlayerId::Four }
-        PlayerId::Four => { PlayerId::One }
+/// The tiles in the player's hand that may be placed on the board right now.
+pub fn legal_tiles(game: &Game, player_id: PlayerId) -> Vec<Tile> {
+    game.players
+        .iter()
+        .find(|p| p.id == player_id)
+        .map_or(Vec::new(), |p| {
+            p.tiles.iter().filter(|t| unplayable_reason(game, t).is_none()).cloned().collect()
+        })
+}
+
+pub fn unplayable_reason(game: &Game, tile: &Tile) -> Option<Unplayable> {
+    let hotels = adjacent_hotels(&game.board, tile);
+    let safe_chains = hotels.iter().filter(|h| hotel_chain_size(game, (*h).clone()) >= game.config.safe_chain_size as usize).count();
+    let founds_chain = hotels.is_empty() && !game.board.placed_neighbors(tile).is_empty();
+    if safe_chains >= 2 {
+        Some(Unplayable::MergesSafeChains)
+    } else if founds_chain && chain_sizes(game).len() == all_hotels().len() {
+        Some(Unplayable::NoChainAvailable)
+    } else {
+        None
     }
 }
 
-fn place_tile(game: &Game, player_id: PlayerId, tile: &Tile) -> Result<Game, String> {
-    if !game_player_has_turn(game, player_id.clone()) {
-        let error_msg = format!("Error placing tile: player {:?} does not have turn", player_id);
-        return Err(error_msg)
+fn is_dead_tile(game: &Game, tile: &Tile) -> bool {
+    unplayable_reason(game, tile) == Some(Unplayable::MergesSafeChains)
+}
+
+/// Resolves the replacement for a dead tile to the tile on top of the bag.
+pub fn replace_dead_tile_action(game: &Game, player: PlayerId, tile: Tile) -> Result<Action, GameError> {
+    match game.draw_pile.last() {
+        Some(replacement) => Ok(Action::ReplaceDeadTile { player: player, tile: tile, replacement: replacement.clone() }),
+        None => Err(GameError::NoTilesLeft)
+    }
+}
+
+/// Before drawing, a player may discard each dead tile in their hand for a new one from the bag.
+fn replace_dead_tile(game: &Game, player_id: PlayerId, tile: &Tile, replacement: &Tile) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    if game.turn_state != TurnState::Drawing && game.turn_state != TurnState::BuyingOrDrawing {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    if !game_player_has_tile(game, player_id, tile) {
+        return Err(GameError::TileNotInHand(player_id, tile.clone()))
     }
-    if !game_player_has_tile(game, player_id.clone(), tile) {
-        let error_msg = format!("Error placing tile: player {:?} does not have tile {:?}", player_id, *tile);
-        return Err(error_msg)
+    if !is_dead_tile(game, tile) {
+        return Err(GameError::TileNotDead(tile.clone()))
     }
-    let new_players = remove_tile_from_player(game.players.clone(), player_id.clone(), tile);
+    let draw_pile = match take_from_bag(&game.draw_pile, replacement) {
+        Ok(draw_pile) => draw_pile,
+        Err(e) => return Err(e)
+    };
+    let new_players = remove_tile_from_player(game.players.clone(), player_id, tile);
+    let new_players = add_tile_to_player(new_players, player_id, replacement);
     Ok(Game {
-        board: place_tile_on_board(&game.board, &tile),
         players: new_players,
-        turn: game.turn.clone(),
-        turn_state: state_after_place_tile(&game.board, &tile)
+        draw_pile: draw_pile,
+        ..game.clone()
     })
 }
 
 fn state_after_place_tile(board: &Board, tile: &Tile) -> TurnState {
-  let mut adjacent_tiles = 0;
-  let mut adjacent_hotels: Vec<Hotel> = Vec::new();
-  for slot in board.slots.clone() {
-    if is_adjacent(&slot, tile) {
-      println!("Found adjacent tile: {}, {}", slot.row, slot.col);
-      if slot.has_tile {
-        adjacent_tiles += 1;
-      }
-      if slot.hotel != None {
-        adjacent_hotels.push(slot.hotel.unwrap());
-      }
+    let adjacent_tiles = board.placed_neighbors(tile).len();
+    let adjacent_hotels = adjacent_hotels(board, tile);
+    if adjacent_tiles == 0 || adjacent_hotels.len() == 1 {
+        TurnState::BuyingOrDrawing
+    } else if adjacent_hotels.len() == 0 {
+        TurnState::CreatingChain
+    } else {
+        TurnState::Merging
     }
-  }
-  println!("Adjacent tiles: {}", adjacent_tiles);
-  println!("Adjacent hotels: {}", adjacent_hotels.len());
-  if adjacent_tiles == 0 || adjacent_hotels.len() == 1 {
-    println!("Buying or drawing.");
-    TurnState::BuyingOrDrawing
-  } else if adjacent_hotels.len() == 0 {
-    println!("Creating chain");
-    TurnState::CreatingChain
-  } else {
-    println!("Merging");
-    TurnState::Merging
-  }
 }
 
-fn is_adjacent(slot: &Slot, tile: &Tile) -> bool {
-  let mut adjacent = false;
-  if tile.row() > 0 {
-    adjacent = slot.col == tile.col() && slot.row == tile.row() - 1;
-  }
-  if tile.col() > 0 {
-    adjacent = adjacent || (slot.row == tile.row() && slot.col == tile.col() - 1)
-  }
-  adjacent || (slot.col == tile.col() && slot.row == tile.row() + 1) ||
-      (slot.row == tile.row() && slot.col == tile.col() + 1)
+fn adjacent_hotels(board: &Board, tile: &Tile) -> Vec<Hotel> {
+    let mut hotels: Vec<Hotel> = Vec::new();
+    for slot in board.placed_neighbors(tile) {
+        if let Some(ref hotel) = slot.hotel {
+            if !hotels.contains(hotel) {
+                hotels.push(hotel.clone());
+            }
+        }
+    }
+    hotels
 }
 
 fn add_tile_to_player(mut players: Vec<Player>, player_id: PlayerId, tile: &Tile) -> Vec<Player> {
@@ -228,96 +626,474 @@ fn player_has_tile(player: &Player, tile: &Tile) -> bool {
     player.tiles.iter().any(|t| *t == *tile)
 }
 
-fn place_tile_on_board(board: &Board, tile: &Tile) -> Board {
-    let slots = board.slots
+fn found_chain(game: &Game, player_id: PlayerId, hotel: Hotel) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    if game.turn_state != TurnState::CreatingChain {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    if hotel_chain_size(game, hotel.clone()) > 0 {
+        return Err(GameError::HotelAlreadyOnBoard(hotel))
+    }
+    let placed_tile = match game.placed_tile {
+        Some(ref tile) => tile.clone(),
+        None => return Err(GameError::WrongPhase(game.turn_state.clone()))
+    };
+    let chain = game.board.connected_group(&placed_tile);
+    let (new_players, bank_shares) = if shares_of(&game.bank_shares, &hotel) > 0 {
+        (add_share_to_player(game.players.clone(), player_id, hotel.clone()),
+         remove_share(game.bank_shares.clone(), hotel.clone()))
+    } else {
+        (game.players.clone(), game.bank_shares.clone())
+    };
+    let mut game_after = Game {
+        players: new_players,
+        bank_shares: bank_shares,
+        turn_state: TurnState::BuyingOrDrawing,
+        ..game.clone()
+    };
+    game_after.board.tag(&chain, &hotel);
+    Ok(game_after)
+}
+
+fn start_merger(game: &Game, chains: Vec<Hotel>) -> Game {
+    let largest = chains.iter().map(|h| hotel_chain_size(game, h.clone())).max().unwrap_or(0);
+    let candidates: Vec<Hotel> = chains
         .iter()
-        .map(|s| {
-            if s.row == tile.row() && s.col == tile.col() {
-                Slot { row: s.row, col: s.col, has_tile: true, hotel: s.hotel.clone() }
+        .filter(|h| hotel_chain_size(game, (*h).clone()) == largest)
+        .cloned()
+        .collect();
+    if candidates.len() > 1 {
+        Game {
+            turn_state: TurnState::ChoosingSurvivor,
+            merger: Some(Merger { chains: chains, survivor: None, defunct: Vec::new(), shareholders: Vec::new() }),
+            ..game.clone()
+        }
+    } else {
+        begin_merger(game, chains, candidates[0].clone())
+    }
+}
+
+fn choose_survivor(game: &Game, player_id: PlayerId, hotel: Hotel) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    let chains = match (&game.turn_state, &game.merger) {
+        (&TurnState::ChoosingSurvivor, &Some(ref merger)) => merger.chains.clone(),
+        _ => {
+            return Err(GameError::WrongPhase(game.turn_state.clone()))
+        }
+    };
+    let largest = chains.iter().map(|h| hotel_chain_size(game, h.clone())).max().unwrap_or(0);
+    if !chains.contains(&hotel) || hotel_chain_size(game, hotel.clone()) != largest {
+        return Err(GameError::NotLargestChain(hotel))
+    }
+    Ok(begin_merger(game, chains, hotel))
+}
+
+/// Orders the defunct chains largest first and starts resolving the first of them.
+fn begin_merger(game: &Game, chains: Vec<Hotel>, survivor: Hotel) -> Game {
+    let mut defunct: Vec<Hotel> = all_hotels()
+        .into_iter()
+        .filter(|h| *h != survivor && chains.contains(h))
+        .collect();
+    defunct.sort_by(|a, b| hotel_chain_size(game, b.clone()).cmp(&hotel_chain_size(game, a.clone())));
+    let merger = Merger { chains: chains, survivor: Some(survivor), defunct: defunct, shareholders: Vec::new() };
+    next_defunct(&Game { merger: Some(merger), ..game.clone() })
+}
+
+/// Lets the mergemaker pick the next defunct chain when several of the largest ones left are
+/// equally large. Otherwise the largest one is resolved right away.
+fn next_defunct(game: &Game) -> Game {
+    let merger = game.merger.clone().unwrap();
+    if largest_defunct(game, &merger).len() > 1 {
+        Game { turn_state: TurnState::ChoosingDefunct, ..game.clone() }
+    } else {
+        resolve_next_defunct(game)
+    }
+}
+
+fn largest_defunct(game: &Game, merger: &Merger) -> Vec<Hotel> {
+    let largest = merger.defunct.first().map_or(0, |h| hotel_chain_size(game, h.clone()));
+    merger.defunct
+        .iter()
+        .filter(|h| hotel_chain_size(game, (*h).clone()) == largest)
+        .cloned()
+        .collect()
+}
+
+fn choose_defunct(game: &Game, player_id: PlayerId, hotel: Hotel) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    let merger = match (&game.turn_state, &game.merger) {
+        (&TurnState::ChoosingDefunct, &Some(ref merger)) => merger.clone(),
+        _ => {
+            return Err(GameError::WrongPhase(game.turn_state.clone()))
+        }
+    };
+    if !largest_defunct(game, &merger).contains(&hotel) {
+        return Err(GameError::NotLargestChain(hotel))
+    }
+    let mut defunct: Vec<Hotel> = merger.defunct.iter().filter(|h| **h != hotel).cloned().collect();
+    defunct.insert(0, hotel);
+    Ok(resolve_next_defunct(&Game {
+        merger: Some(Merger { defunct: defunct, ..merger }),
+        ..game.clone()
+    }))
+}
+
+/// Pays the bonuses for the next defunct chain and asks its shareholders what to do with
+/// their stock. Once every defunct chain is absorbed the merged chain is retagged and the
+/// mergemaker continues with buying.
+fn resolve_next_defunct(game: &Game) -> Game {
+    let merger = game.merger.clone().unwrap();
+    let survivor = merger.survivor.clone().unwrap();
+    match merger.defunct.first().cloned() {
+        None => {
+            let placed_tile = game.placed_tile.clone().unwrap();
+            let chain = game.board.connected_group(&placed_tile);
+            let mut game_after = Game {
+                turn_state: TurnState::BuyingOrDrawing,
+                merger: None,
+                ..game.clone()
+            };
+            game_after.board.tag(&chain, &survivor);
+            game_after
+        }
+        Some(defunct) => {
+            let players = pay_bonuses(game, defunct.clone());
+            let shareholders: Vec<PlayerId> = players_in_turn_order(game)
+                .into_iter()
+                .filter(|id| {
+                    players.iter().any(|p| p.id == *id && shares_of(&p.shares, &defunct) > 0)
+                })
+                .collect();
+            let game_after = Game { players: players, ..game.clone() };
+            if shareholders.is_empty() {
+                absorb_defunct(&game_after)
             } else {
-                s.clone()
+                Game {
+                    turn_state: TurnState::Merging,
+                    merger: Some(Merger { shareholders: shareholders, ..merger }),
+                    ..game_after
+                }
             }
+        }
+    }
+}
+
+/// Retags the current defunct chain with the surviving hotel and moves on to the next one.
+fn absorb_defunct(game: &Game) -> Game {
+    let merger = game.merger.clone().unwrap();
+    let survivor = merger.survivor.clone().unwrap();
+    let defunct = merger.defunct[0].clone();
+    let defunct_tiles: Vec<Tile> = game.board.slots
+        .iter()
+        .filter(|s| s.has_tile && s.hotel == Some(defunct.clone()))
+        .map(|s| Tile { row: s.row, col: s.col })
+        .collect();
+    let remaining = merger.defunct[1..].to_vec();
+    let mut game_after = Game {
+        merger: Some(Merger { defunct: remaining, shareholders: Vec::new(), ..merger }),
+        ..game.clone()
+    };
+    game_after.board.tag(&defunct_tiles, &survivor);
+    next_defunct(&game_after)
+}
+
+fn handle_merge_stocks(game: &Game, player_id: PlayerId, hold: u8, sell: u8, trade: u8) -> Result<Game, GameError> {
+    let merger = match (&game.turn_state, &game.merger) {
+        (&TurnState::Merging, &Some(ref merger)) => merger.clone(),
+        _ => {
+            return Err(GameError::WrongPhase(game.turn_state.clone()))
+        }
+    };
+    if merger.shareholders.first() != Some(&player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    let survivor = merger.survivor.clone().unwrap();
+    let defunct = merger.defunct[0].clone();
+    let player_index = game.players.iter().position(|p| p.id == player_id).unwrap();
+    let player = game.players[player_index].clone();
+    let owned = shares_of(&player.shares, &defunct);
+    let decided = hold as u32 + sell as u32 + trade as u32;
+    if decided != owned as u32 {
+        return Err(GameError::MergeSharesMismatch { owned: owned, decided: decided })
+    }
+    if trade % 2 != 0 {
+        return Err(GameError::OddTrade(trade))
+    }
+    let survivor_in_bank = shares_of(&game.bank_shares, &survivor);
+    if survivor_in_bank < trade / 2 {
+        return Err(GameError::BankOutOfShares(survivor))
+    }
+    let sale = sell as i32 * share_price(game, Some(defunct.clone()));
+    let defunct_in_bank = shares_of(&game.bank_shares, &defunct) + sell + trade;
+    let bank_shares = set_shares(game.bank_shares.clone(), defunct.clone(), defunct_in_bank);
+    let bank_shares = set_shares(bank_shares, survivor.clone(), survivor_in_bank - trade / 2);
+    let shares = set_shares(player.shares.clone(), defunct, hold);
+    let survivor_count = shares_of(&shares, &survivor) + trade / 2;
+    let mut new_players = game.players.clone();
+    new_players[player_index] = Player {
+        money: player.money + sale,
+        shares: set_shares(shares, survivor, survivor_count),
+        ..player
+    };
+    let remaining = merger.shareholders[1..].to_vec();
+    let game_after = Game {
+        players: new_players,
+        bank_shares: bank_shares,
+        merger: Some(Merger { shareholders: remaining.clone(), ..merger }),
+        ..game.clone()
+    };
+    if remaining.is_empty() {
+        Ok(absorb_defunct(&game_after))
+    } else {
+        Ok(game_after)
+    }
+}
+
+/// Pays the majority and minority shareholder bonuses of `hotel`.
+fn pay_bonuses(game: &Game, hotel: Hotel) -> Vec<Player> {
+    let bonuses = bonuses(game, hotel);
+    game.players
+        .iter()
+        .map(|p| {
+            let bonus: i32 = bonuses.iter().filter(|&&(id, _)| id == p.id).map(|&(_, b)| b).sum();
+            Player { money: p.money + bonus, ..p.clone() }
+        })
+        .collect()
+}
+
+/// Majority and minority bonuses are the config's `majority_bonus` and `minority_bonus` times the
+/// share price. Tied holders split the bonuses they share, rounded up to the nearest 100, and a
+/// sole shareholder takes both.
+pub fn bonuses(game: &Game, hotel: Hotel) -> Vec<(PlayerId, i32)> {
+    let price = share_price(game, Some(hotel.clone()));
+    let majority_bonus = game.config.majority_bonus * price;
+    let minority_bonus = game.config.minority_bonus * price;
+    let holdings: Vec<(PlayerId, u8)> = game.players
+        .iter()
+        .map(|p| (p.id, shares_of(&p.shares, &hotel)))
+        .filter(|&(_, count)| count > 0)
+        .collect();
+    let most = holdings.iter().map(|&(_, count)| count).max().unwrap_or(0);
+    let majority: Vec<PlayerId> = holdings.iter().filter(|&&(_, c)| c == most).map(|&(id, _)| id).collect();
+    if majority.is_empty() {
+        return Vec::new()
+    }
+    let second = holdings.iter().map(|&(_, count)| count).filter(|&c| c < most).max();
+    match second {
+        Some(second) if majority.len() == 1 => {
+            let minority: Vec<PlayerId> = holdings.iter().filter(|&&(_, c)| c == second).map(|&(id, _)| id).collect();
+            let mut payouts = vec![(majority[0], majority_bonus)];
+            payouts.extend(split_bonus(minority, minority_bonus));
+            payouts
+        }
+        _ => split_bonus(majority, majority_bonus + minority_bonus)
+    }
+}
+
+fn split_bonus(players: Vec<PlayerId>, bonus: i32) -> Vec<(PlayerId, i32)> {
+    let ways = players.len() as i32;
+    let share = (bonus + 100 * ways - 1) / (100 * ways) * 100;
+    players.into_iter().map(|id| (id, share)).collect()
+}
+
+/// The game may be ended once a chain reaches 41 tiles or every chain on the board is safe.
+pub fn can_end_game(game: &Game) -> bool {
+    let sizes: Vec<usize> = chain_sizes(game).values().cloned().collect();
+    sizes.iter().any(|&size| size >= game.config.end_chain_size as usize) ||
+        (!sizes.is_empty() && sizes.iter().all(|&size| size >= game.config.safe_chain_size as usize))
+}
+
+fn end_game(game: &Game, player_id: PlayerId) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player_id) {
+        return Err(GameError::NotYourTurn(player_id))
+    }
+    match game.turn_state {
+        TurnState::Placing | TurnState::BuyingOrDrawing | TurnState::Drawing => {}
+        _ => {
+            return Err(GameError::WrongPhase(game.turn_state.clone()))
+        }
+    }
+    if !can_end_game(game) {
+        return Err(GameError::CannotEndGame)
+    }
+    let hotels: Vec<Hotel> = all_hotels().into_iter().filter(|h| hotel_chain_size(game, h.clone()) > 0).collect();
+    let with_bonuses = hotels.iter().fold(game.clone(), |g, h| {
+        Game { players: pay_bonuses(&g, h.clone()), ..g.clone() }
+    });
+    let players: Vec<Player> = with_bonuses.players
+        .iter()
+        .map(|p| {
+            let sale: i32 = hotels
+                .iter()
+                .map(|h| shares_of(&p.shares, h) as i32 * share_price(game, Some(h.clone())))
+                .sum();
+            Player { money: p.money + sale, shares: empty_shares(), ..p.clone() }
+        })
+        .collect();
+    let final_score = rank_players(&players);
+    Ok(Game {
+        players: players,
+        bank_shares: full_bank(&game.config),
+        turn_state: TurnState::GameOver,
+        final_score: Some(final_score),
+        ..with_bonuses
+    })
+}
+
+/// Richest player first. Players with equal money share a rank.
+fn rank_players(players: &Vec<Player>) -> FinalScore {
+    let mut by_money: Vec<&Player> = players.iter().collect();
+    by_money.sort_by(|a, b| b.money.cmp(&a.money));
+    let standings = by_money
+        .iter()
+        .map(|p| {
+            let richer = players.iter().filter(|other| other.money > p.money).count();
+            Standing { rank: (richer + 1) as u8, player: p.id, money: p.money }
         })
         .collect();
-    Board { slots: slots }
+    FinalScore { standings: standings }
 }
 
-fn buy_stocks(game: &Game, player: PlayerId, hotel1: Option<Hotel>, hotel2: Option<Hotel>, hotel3: Option<Hotel>) -> Result<Game, String> {
+fn players_in_turn_order(game: &Game) -> Vec<PlayerId> {
+    let mut order = vec![game.turn];
+    while order.len() < game.players.len() {
+        let next = next_turn(game, order[order.len() - 1]);
+        order.push(next);
+    }
+    order
+}
+
+fn add_share_to_player(mut players: Vec<Player>, player_id: PlayerId, hotel: Hotel) -> Vec<Player> {
+    let player_index = players.iter().position(|p| p.id == player_id).unwrap();
+    players[player_index].shares = add_share(players[player_index].shares.clone(), hotel);
+    players
+}
+
+fn buy_stocks(game: &Game, player: PlayerId, hotels: &Vec<Hotel>) -> Result<Game, GameError> {
+    if !game_player_has_turn(game, player) {
+        return Err(GameError::NotYourTurn(player))
+    }
+    if game.turn_state != TurnState::BuyingOrDrawing {
+        return Err(GameError::WrongPhase(game.turn_state.clone()))
+    }
+    if hotels.len() > game.config.max_stock_purchase as usize {
+        return Err(GameError::TooManyShares { max: game.config.max_stock_purchase })
+    }
+    for hotel in hotels.iter() {
+        if hotel_chain_size(game, hotel.clone()) == 0 {
+            return Err(GameError::HotelNotOnBoard(hotel.clone()))
+        }
+    }
+    let total_cost: i32 = hotels.iter().map(|h| share_price(game, Some(h.clone()))).sum();
+    let money = game.players.iter().find(|p| p.id == player).map_or(0, |p| p.money);
+    if total_cost > money {
+        return Err(GameError::InsufficientFunds { cost: total_cost, money: money })
+    }
+    for hotel in hotels.iter() {
+        let wanted = hotels.iter().filter(|h| *h == hotel).count();
+        if (shares_of(&game.bank_shares, hotel) as usize) < wanted {
+            return Err(GameError::BankOutOfShares(hotel.clone()))
+        }
+    }
+    let bank_shares = hotels.iter().fold(game.bank_shares.clone(), |bank, h| remove_share(bank, h.clone()));
     let new_players: Vec<Player> = game.players
         .iter()
         .map(|p| {
             if p.id == player {
-                player_buy_stocks(&game, &p, hotel1.clone(), hotel2.clone(), hotel3.clone())
+                player_buy_stocks(&game, &p, hotels)
             } else {
                 p.clone()
             }
         })
         .collect();
     Ok(Game {
-        board: game.board.clone(),
         players: new_players,
-        turn: game.turn.clone(),
-        turn_state: TurnState::Drawing
+        bank_shares: bank_shares,
+        turn_state: TurnState::Drawing,
+        ..game.clone()
     })
 }
 
-fn player_buy_stocks(game: &Game, player: &Player, hotel1: Option<Hotel>, hotel2: Option<Hotel>, hotel3: Option<Hotel>) -> Player {
-  let new_shares = vec![hotel1.clone(), hotel2.clone(), hotel3.clone()]
+fn player_buy_stocks(game: &Game, player: &Player, hotels: &Vec<Hotel>) -> Player {
+  let new_shares = hotels
         .iter()
-        .fold(player.shares.clone(), | shares, hotel: &Option<Hotel> | {
-            match *hotel {
-                Some(ref h) => { add_share(shares, h.clone()) }
-                None => { shares }
-            }
-        });
-  let total_cost = share_price(game, hotel1) + share_price(game, hotel2) + share_price(game, hotel3);
+        .fold(player.shares.clone(), | shares, hotel: &Hotel | add_share(shares, hotel.clone()));
+  let total_cost: i32 = hotels.iter().map(|h| share_price(game, Some(h.clone()))).sum();
   let money_after = player.money - total_cost;
   Player {
-      id: player.id.clone(),
       money: money_after,
       shares: new_shares,
-      tiles: player.tiles.clone()
+      ..player.clone()
   }
 }
 
 fn share_price(game: &Game, hotel: Option<Hotel>) -> i32 {
-  hotel.map(|h| stock_price(h.clone(), hotel_chain_size(game, h.clone()))).unwrap_or(0)
+  hotel.map(|h| stock_price(&game.config, h.clone(), hotel_chain_size(game, h.clone()))).unwrap_or(0)
 }
 
 fn add_share(shares: PlayerShares, hotel: Hotel) -> PlayerShares {
-    let mut new_shares = PlayerShares {
-        luxor: shares.luxor,
-        tower: shares.tower,
-        american: shares.american,
-        festival: shares.festival,
-        worldwide: shares.worldwide,
-        continental: shares.continental,
-        imperial: shares.imperial
-    };
+    let count = shares_of(&shares, &hotel);
+    set_shares(shares, hotel, count + 1)
+}
+
+fn remove_share(shares: PlayerShares, hotel: Hotel) -> PlayerShares {
+    let count = shares_of(&shares, &hotel);
+    set_shares(shares, hotel, count - 1)
+}
+
+fn shares_of(shares: &PlayerShares, hotel: &Hotel) -> u8 {
+    match *hotel {
+        Hotel::Tower =>       { shares.tower }
+        Hotel::Luxor =>       { shares.luxor }
+        Hotel::American =>    { shares.american }
+        Hotel::Worldwide =>   { shares.worldwide }
+        Hotel::Festival =>    { shares.festival }
+        Hotel::Imperial =>    { shares.imperial }
+        Hotel::Continental => { shares.continental }
+    }
+}
+
+fn set_shares(shares: PlayerShares, hotel: Hotel, count: u8) -> PlayerShares {
+    let mut new_shares = shares;
     match hotel {
-        Hotel::Tower =>       { new_shares.tower += 1 }
-        Hotel::Luxor =>       { new_shares.luxor += 1 }
-        Hotel::American =>    { new_shares.american += 1 }
-        Hotel::Worldwide =>   { new_shares.worldwide += 1 }
-        Hotel::Festival =>    { new_shares.festival += 1 }
-        Hotel::Imperial =>    { new_shares.imperial += 1 }
-        Hotel::Continental => { new_shares.continental += 1 }
+        Hotel::Tower =>       { new_shares.tower = count }
+        Hotel::Luxor =>       { new_shares.luxor = count }
+        Hotel::American =>    { new_shares.american = count }
+        Hotel::Worldwide =>   { new_shares.worldwide = count }
+        Hotel::Festival =>    { new_shares.festival = count }
+        Hotel::Imperial =>    { new_shares.imperial = count }
+        Hotel::Continental => { new_shares.continental = count }
     };
     new_shares
 }
 
-fn hotel_chain_size(game: &Game, hotel: Hotel) -> u8 {
-  2
+pub fn all_hotels() -> Vec<Hotel> {
+    vec![Hotel::Luxor, Hotel::Tower, Hotel::American, Hotel::Festival,
+         Hotel::Worldwide, Hotel::Continental, Hotel::Imperial]
+}
+
+pub fn chain_sizes(game: &Game) -> HashMap<Hotel, usize> {
+    game.board.chain_sizes()
+}
+
+fn hotel_chain_size(game: &Game, hotel: Hotel) -> usize {
+    game.board.chain_size(&hotel)
 }
 
-fn stock_price(hotel: Hotel, num_tiles: u8) -> i32 {
-    base_price(hotel) + 100 * price_level(num_tiles) as i32
+fn stock_price(config: &GameConfig, hotel: Hotel, num_tiles: usize) -> i32 {
+    base_price(config, hotel) + config.price_step * price_level(config, num_tiles) as i32
 }
 
-fn base_price(hotel: Hotel) -> i32 {
-    let cheap = 200;
-    let medium = 300;
-    let spendy = 400;
+fn base_price(config: &GameConfig, hotel: Hotel) -> i32 {
+    let cheap = config.cheap_price;
+    let medium = config.medium_price;
+    let spendy = config.expensive_price;
     match hotel {
         Hotel::Tower =>       { cheap }
         Hotel::Luxor =>       { cheap }
@@ -329,24 +1105,7 @@ fn base_price(hotel: Hotel) -> i32 {
     }
 }
 
-fn price_level(num_tiles: u8) -> u8 {
-  if num_tiles == 2 {
-      0
-  } else if num_tiles == 3 {
-      1
-  } else if num_tiles == 4 {
-      2
-  } else if num_tiles == 5 {
-      3
-  } else if num_tiles >= 6 && num_tiles <= 10 {
-      4
-  } else if num_tiles >= 11 && num_tiles <= 20 {
-      5
-  } else if num_tiles >= 21 && num_tiles <= 30 {
-      6
-  } else if num_tiles >= 31 && num_tiles <= 40 {
-      7
-  } else { // >= 41
-      8
-  }
+fn price_level(config: &GameConfig, num_tiles: usize) -> u8 {
+    let reached = config.price_levels.iter().filter(|&&min_size| num_tiles >= min_size as usize).count();
+    reached.saturating_sub(1) as u8
 }
@@ -0,0 +1,253 @@
+extern crate rustc_serialize;
+
+use game;
+use types::{Action, Game, GameError};
+
+use std::fmt;
+use std::fs;
+use std::fs::{File, OpenOptions};
+use std::io;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+use rustc_serialize::json;
+
+const EXTENSION: &'static str = "journal";
+const SNAPSHOT_EXTENSION: &'static str = "snapshot";
+
+/// First line of a journal: which game it records and how that game started.
+#[derive(RustcDecodable, RustcEncodable)]
+struct Header {
+    game: u32,
+    initial_game: Game
+}
+
+/// Every later line is one accepted action. `seq` counts actions from 1 so that lost lines show
+/// up as gaps, and `token` is the secret handed out when the action seated a player.
+#[derive(RustcDecodable, RustcEncodable)]
+struct Entry {
+    seq: u32,
+    action: Action,
+    token: Option<String>
+}
+
+/// The game as it was after the first `seq` actions of its journal. It spares a restart from
+/// replaying the whole journal; the journal stays the record the snapshot is checked against.
+#[derive(RustcDecodable, RustcEncodable)]
+struct Snapshot {
+    seq: u32,
+    game: Game
+}
+
+/// A game read back from its journal. Its actions replay cleanly on top of `initial_game`.
+pub struct Recorded {
+    pub id: u32,
+    pub initial_game: Game,
+    /// Accepted actions in order, each with the token it handed out, if any.
+    pub actions: Vec<(Action, Option<String>)>,
+    /// The game after the last action.
+    pub game: Game,
+    /// How many actions were replayed to get `game`. Fewer than all of them when a snapshot was used.
+    pub replayed: usize,
+    /// The last line of the file when a crash cut it short. It was never acknowledged, so it is
+    /// dropped from the journal.
+    pub cut_short: Option<usize>
+}
+
+#[derive(Debug)]
+pub enum JournalError {
+    Io(io::Error),
+    /// The file ends in the middle of its first line, so not even the game it records is known.
+    Truncated { line: usize },
+    /// A line that is not what the journal should hold at that point.
+    Corrupt { line: usize, reason: String },
+    /// An action the game refuses, so the journal does not describe a game that was played.
+    Replay { line: usize, error: GameError },
+    /// Replaying the whole journal does not give the game stored in the snapshot.
+    SnapshotMismatch { seq: u32 }
+}
+
+impl fmt::Display for JournalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            JournalError::Io(ref e) => write!(f, "{}", e),
+            JournalError::Truncated { line } => write!(f, "line {} is cut short", line),
+            JournalError::Corrupt { line, ref reason } => write!(f, "line {} is corrupt: {}", line, reason),
+            JournalError::Replay { line, ref error } => write!(f, "line {} does not replay: {}", line, error),
+            JournalError::SnapshotMismatch { seq } => write!(f, "the snapshot after action {} differs from the replay", seq)
+        }
+    }
+}
+
+impl From<io::Error> for JournalError {
+    fn from(e: io::Error) -> JournalError {
+        JournalError::Io(e)
+    }
+}
+
+/// The append-only file recording one game, and the latest snapshot of it.
+pub struct Journal {
+    path: PathBuf,
+    snapshot_path: PathBuf,
+    next_seq: u32
+}
+
+impl Journal {
+    /// Starts the journal of a new game. Fails rather than overwrite an existing journal.
+    pub fn create(dir: &Path, id: u32, initial_game: &Game) -> io::Result<Journal> {
+        try!(fs::create_dir_all(dir));
+        let path = journal_path(dir, id);
+        let mut file = try!(OpenOptions::new().write(true).create_new(true).open(&path));
+        let header = Header { game: id, initial_game: initial_game.clone() };
+        try!(write_line(&mut file, &header));
+        Ok(Journal { path: path, snapshot_path: snapshot_path(dir, id), next_seq: 1 })
+    }
+
+    /// Records an accepted action. The line is on disk before this returns. When the write fails,
+    /// whatever part of the line made it to the file is cut off again.
+    pub fn append(&mut self, action: &Action, token: Option<&str>) -> io::Result<()> {
+        let entry = Entry { seq: self.next_seq, action: action.clone(), token: token.map(|t| t.to_string()) };
+        let mut file = try!(OpenOptions::new().append(true).open(&self.path));
+        let len = try!(file.metadata()).len();
+        if let Err(e) = write_line(&mut file, &entry) {
+            let _ = file.set_len(len);
+            return Err(e)
+        }
+        self.next_seq += 1;
+        Ok(())
+    }
+
+    /// Stores `game` as the state after every action appended so far, replacing the previous
+    /// snapshot. The new snapshot is written aside and renamed, so a crash leaves the old one whole.
+    pub fn snapshot(&self, game: &Game) -> io::Result<()> {
+        let snapshot = Snapshot { seq: self.next_seq - 1, game: game.clone() };
+        let partial = self.snapshot_path.with_extension("partial");
+        let mut file = try!(File::create(&partial));
+        try!(write_line(&mut file, &snapshot));
+        fs::rename(&partial, &self.snapshot_path)
+    }
+}
+
+fn journal_path(dir: &Path, id: u32) -> PathBuf {
+    dir.join(format!("{}.{}", id, EXTENSION))
+}
+
+fn snapshot_path(dir: &Path, id: u32) -> PathBuf {
+    dir.join(format!("{}.{}", id, SNAPSHOT_EXTENSION))
+}
+
+fn write_line<T: rustc_serialize::Encodable>(file: &mut File, value: &T) -> io::Result<()> {
+    let encoded = match json::encode(value) {
+        Ok(encoded) => encoded,
+        Err(e) => return Err(io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+    };
+    try!(file.write_all(encoded.as_bytes()));
+    try!(file.write_all(b"\n"));
+    file.sync_data()
+}
+
+/// Ids of the journals in `dir`, in ascending order. A missing directory holds no journals.
+pub fn journal_ids(dir: &Path) -> io::Result<Vec<u32>> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e)
+    };
+    let mut ids = Vec::new();
+    for entry in entries {
+        let path = try!(entry).path();
+        if path.extension().map_or(false, |ext| ext == EXTENSION) {
+            if let Some(id) = path.file_stem().and_then(|s| s.to_str()).and_then(|s| s.parse().ok()) {
+                ids.push(id);
+            }
+        }
+    }
+    ids.sort();
+    Ok(ids)
+}
+
+/// Reads the journal of game `id` and replays the actions after its snapshot. A missing or
+/// unreadable snapshot means replaying every action. With `verify`, every action is replayed
+/// regardless and the result must match the snapshot. A last line cut short by a crash is left
+/// out, and cut off the file once the rest has replayed so that new actions follow whole lines.
+pub fn load(dir: &Path, id: u32, verify: bool) -> Result<(Recorded, Journal), JournalError> {
+    let path = journal_path(dir, id);
+    let mut contents = String::new();
+    try!(try!(File::open(&path)).read_to_string(&mut contents));
+    let (initial_game, entries, cut_short) = try!(parse(id, &contents));
+    let (seq, game) = match read_snapshot(dir, id) {
+        Some(snapshot) if snapshot.seq as usize <= entries.len() => {
+            let seq = snapshot.seq as usize;
+            let game = try!(replay(&snapshot.game, &entries[seq..]));
+            if verify && try!(replay(&initial_game, &entries)) != game {
+                return Err(JournalError::SnapshotMismatch { seq: snapshot.seq })
+            }
+            (seq, game)
+        }
+        _ => (0, try!(replay(&initial_game, &entries)))
+    };
+    let recorded = Recorded {
+        id: id,
+        initial_game: initial_game,
+        replayed: entries.len() - seq,
+        game: game,
+        actions: entries.into_iter().map(|e| (e.action, e.token)).collect(),
+        cut_short: cut_short
+    };
+    if cut_short.is_some() {
+        let intact = contents.rfind('\n').map_or(0, |end| end + 1);
+        try!(try!(OpenOptions::new().write(true).open(&path)).set_len(intact as u64));
+    }
+    let journal = Journal { path: path, snapshot_path: snapshot_path(dir, id), next_seq: recorded.actions.len() as u32 + 1 };
+    Ok((recorded, journal))
+}
+
+fn read_snapshot(dir: &Path, id: u32) -> Option<Snapshot> {
+    let mut contents = String::new();
+    match File::open(snapshot_path(dir, id)).and_then(|mut f| f.read_to_string(&mut contents)) {
+        Ok(_) => json::decode(contents.trim_right()).ok(),
+        Err(_) => None
+    }
+}
+
+fn replay(from: &Game, entries: &[Entry]) -> Result<Game, JournalError> {
+    let mut game = from.clone();
+    for entry in entries {
+        game = match game::play_turn(&game, &entry.action) {
+            Ok(game) => game,
+            Err(e) => return Err(JournalError::Replay { line: entry.seq as usize + 1, error: e })
+        };
+    }
+    Ok(game)
+}
+
+/// Decodes the header and entries of a journal, checking that no line is missing. A last line
+/// without its newline is left out, and its number returned with the entries.
+fn parse(id: u32, contents: &str) -> Result<(Game, Vec<Entry>, Option<usize>), JournalError> {
+    let intact = match contents.rfind('\n') {
+        Some(end) => &contents[..end + 1],
+        None => return Err(JournalError::Truncated { line: 1 })
+    };
+    let lines: Vec<&str> = intact.split_terminator('\n').collect();
+    let cut_short = if intact.len() < contents.len() { Some(lines.len() + 1) } else { None };
+    let header: Header = match lines.first().map(|l| json::decode(l)) {
+        Some(Ok(header)) => header,
+        Some(Err(e)) => return Err(JournalError::Corrupt { line: 1, reason: e.to_string() }),
+        None => return Err(JournalError::Truncated { line: 1 })
+    };
+    if header.game != id {
+        return Err(JournalError::Corrupt { line: 1, reason: format!("it records game {}", header.game) })
+    }
+    let mut entries = Vec::new();
+    for (i, l) in lines.iter().enumerate().skip(1) {
+        let line = i + 1;
+        let entry: Entry = match json::decode(l) {
+            Ok(entry) => entry,
+            Err(e) => return Err(JournalError::Corrupt { line: line, reason: e.to_string() })
+        };
+        if entry.seq != i as u32 {
+            return Err(JournalError::Corrupt { line: line, reason: format!("expected action {}, found {}", i, entry.seq) })
+        }
+        entries.push(entry);
+    }
+    Ok((header.initial_game, entries, cut_short))
+}
@@ -1,18 +1,20 @@
 extern crate rustc_serialize;
 
-mod game;
+pub mod game;
+pub mod journal;
 pub mod types;
 pub mod server;
 
 use game::*;
 use types::*;
+use std::collections::HashSet;
 
-type BoardTiles = [[i32; COLS as usize]; ROWS as usize];
-type PlayerTiles = [[(i32,i32); 6]; PLAYERS as usize];
+type BoardTiles = [[i32; 12]; 9];
+type PlayerTiles = [[(i32,i32); 6]; 4];
 
 #[test]
 fn players_start_with_six_tiles() {
-    let game = new_game();
+    let game = new_game(GameConfig::classic(), 4).unwrap();
     for player in game.players {
         assert_eq!(player.tiles.len(), 6);
     }
@@ -20,7 +22,7 @@ fn players_start_with_six_tiles() {
 
 #[test]
 fn players_start_with_6000_in_cash() {
-    let game = new_game();
+    let game = new_game(GameConfig::classic(), 4).unwrap();
     for player in game.players {
         assert_eq!(player.money, 6000);
     }
@@ -28,7 +30,7 @@ fn players_start_with_6000_in_cash() {
 
 #[test]
 fn players_start_with_zero_shares() {
-    let game = new_game();
+    let game = new_game(GameConfig::classic(), 4).unwrap();
     for player in game.players {
         assert_eq!(player.shares.luxor, 0);
         assert_eq!(player.shares.tower, 0);
@@ -40,13 +42,55 @@ fn players_start_with_zero_shares() {
     }
 }
 
+#[test]
+fn games_can_have_two_to_six_players() {
+    assert_eq!(new_game(GameConfig::classic(), 2).unwrap().players.len(), 2);
+    assert_eq!(new_game(GameConfig::classic(), 6).unwrap().players.len(), 6);
+    assert_eq!(new_game(GameConfig::classic(), 1).err(), Some(GameError::InvalidPlayerCount(1)));
+    assert_eq!(new_game(GameConfig::classic(), 7).err(), Some(GameError::InvalidPlayerCount(7)));
+}
+
+#[test]
+fn turn_passes_back_to_first_player_after_last() {
+    let mut game = new_game_with_seed(GameConfig::classic(), 2, 42).unwrap();
+    game.turn = 2;
+    game.turn_state = TurnState::Drawing;
+    let action = draw_action(&game, 2).unwrap();
+    assert_eq!(play_turn(&game, &action).unwrap().turn, 1);
+}
+
 #[test]
 fn game_starts_with_four_placed_tiles() {
-    let game = new_game();
+    let game = new_game(GameConfig::classic(), 4).unwrap();
     let board_tiles = game.board.slots.iter().filter(|s| s.has_tile).count();
     assert_eq!(board_tiles, 4)
 }
 
+#[test]
+fn player_with_tile_closest_to_1a_starts() {
+    let mut lobby = new_lobby(GameConfig::classic(), 4, 1).unwrap();
+    // The top four tiles of the bag go to players 1 to 4: 3A, 1I, 2A and 5C.
+    let draws: Vec<Tile> = [(0, 2), (8, 0), (0, 1), (2, 4)]
+        .iter()
+        .map(|&(r, c)| Tile::new(r, c, &lobby.config).unwrap())
+        .collect();
+    lobby.draw_pile.retain(|t| !draws.contains(t));
+    lobby.draw_pile.extend(draws.iter().cloned());
+    let mut actions = Vec::new();
+    for player in 1..5 {
+        actions.push(Action::Join { player: player, name: format!("Player {}", player) });
+        actions.push(Action::Ready { player: player, ready: true });
+    }
+    actions.push(Action::StartGame { player: 1 });
+    let game = compute_state(&lobby, &actions).unwrap();
+    assert_eq!(game.turn, 2);
+    let drawn: Vec<(PlayerId, Tile)> = game.starting_draw.iter().map(|s| (s.player, s.tile.clone())).collect();
+    assert_eq!(drawn, vec![(1, draws[0].clone()), (2, draws[1].clone()), (3, draws[2].clone()), (4, draws[3].clone())]);
+    for draw in game.starting_draw.iter() {
+        assert!(game.board.has_tile(&draw.tile), "Starting tile was not placed on the board");
+    }
+}
+
 #[test]
 fn placing_a_tile_adds_tile_to_board() {
     let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
@@ -72,10 +116,10 @@ fn placing_a_tile_adds_tile_to_board() {
     [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
     [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
     let game = new_game_with_tiles(start_tiles, player_tiles);
-    let tile_to_place = Tile::new(0,2).unwrap();
-    let action = Action::PlaceTile { player: PlayerId::One, tile: tile_to_place };
+    let tile_to_place = Tile::new(0, 2, &game.config).unwrap();
+    let action = Action::PlaceTile { player: 1, tile: tile_to_place };
     match play_turn(&game, &action) {
-        TurnResult::Success(game_after) => {
+        Ok(game_after) => {
             assert_boards_equal(&tiles_to_board(&end_tiles), &game_after.board);
         }
         _ => {
@@ -100,10 +144,10 @@ fn placing_a_tile_fails_if_player_does_not_have_tile() {
     [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
     [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
     let game = new_game_with_tiles(start_tiles, player_tiles);
-    let tile_to_place = Tile::new(5,11).unwrap();
-    let action = Action::PlaceTile { player: PlayerId::One, tile: tile_to_place };
+    let tile_to_place = Tile::new(5, 11, &game.config).unwrap();
+    let action = Action::PlaceTile { player: 1, tile: tile_to_place };
     match play_turn(&game, &action) {
-        TurnResult::Success(_) => {
+        Ok(_) => {
             panic!("Placing a tile succeeded when player did not have tile")
         }
         _ => {}
@@ -126,10 +170,10 @@ fn placing_a_tile_fails_if_player_does_not_have_turn() {
     [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
     [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
     let game = new_game_with_tiles(start_tiles, player_tiles);
-    let tile_to_place = Tile::new(1,4).unwrap();
-    let action = Action::PlaceTile { player: PlayerId::Two, tile: tile_to_place };
+    let tile_to_place = Tile::new(1, 4, &game.config).unwrap();
+    let action = Action::PlaceTile { player: 2, tile: tile_to_place };
     match play_turn(&game, &action) {
-        TurnResult::Success(_) => {
+        Ok(_) => {
             panic!("Placing a tile succeeded when player did not have turn")
         }
         _ => {}
@@ -152,11 +196,11 @@ fn placing_a_tile_removes_tile_from_player() {
     [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
     [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
     let game = new_game_with_tiles(start_tiles, player_tiles);
-    let tile_to_place = Tile::new(0,2).unwrap();
-    let action = Action::PlaceTile { player: PlayerId::One, tile: tile_to_place.clone() };
+    let tile_to_place = Tile::new(0, 2, &game.config).unwrap();
+    let action = Action::PlaceTile { player: 1, tile: tile_to_place.clone() };
     match play_turn(&game, &action) {
-        TurnResult::Success(game_after) => {
-            let player = game_after.players.iter().find(|p| p.id == PlayerId::One).unwrap();
+        Ok(game_after) => {
+            let player = game_after.players.iter().find(|p| p.id == 1).unwrap();
             let has_tile = player.tiles.iter().any(|t| *t == tile_to_place);
             assert!(!has_tile, "Placed tile was still on player")
         }
@@ -173,7 +217,7 @@ fn buying_stocks_reduces_player_money() {
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-        [0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
@@ -181,11 +225,12 @@ fn buying_stocks_reduces_player_money() {
     [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
     [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
     [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
-    let game = new_game_with_tiles(start_tiles, player_tiles);
-    let action = Action::BuyStocks { player: PlayerId::One, hotel1: Some(Hotel::Luxor), hotel2: None, hotel3: None };
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4)]);
+    let game = in_state(game, TurnState::BuyingOrDrawing);
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Luxor] };
     match play_turn(&game, &action) {
-        TurnResult::Success(game_after) => {
-            let player = game_after.players.iter().find(|p| p.id == PlayerId::One).unwrap();
+        Ok(game_after) => {
+            let player = game_after.players.iter().find(|p| p.id == 1).unwrap();
             let expected_money = 5800;
             let error_msg = format!("After buying stocks, expected player to have {:?} dollars but player had {:?} dollars", expected_money, player.money);
             assert!(player.money == expected_money, error_msg)
@@ -201,7 +246,7 @@ fn buying_stocks_gives_player_bought_stocks() {
     let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
-        [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0],
         [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
@@ -212,15 +257,13 @@ fn buying_stocks_gives_player_bought_stocks() {
     [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
     [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
     let game = new_game_with_tiles(start_tiles, player_tiles);
-    let action = Action::BuyStocks {
-        player: PlayerId::One,
-        hotel1: Some(Hotel::Luxor),
-        hotel2: Some(Hotel::Luxor),
-        hotel3: Some(Hotel::Imperial)
-    };
+    let game = with_hotel(game, Hotel::Imperial, &[(3,2), (3,3)]);
+    let game = with_hotel(game, Hotel::Luxor, &[(5,3), (5,4), (5,5)]);
+    let game = in_state(game, TurnState::BuyingOrDrawing);
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Luxor, Hotel::Luxor, Hotel::Imperial] };
     match play_turn(&game, &action) {
-        TurnResult::Success(game_after) => {
-            let player = game_after.players.iter().find(|p| p.id == PlayerId::One).unwrap();
+        Ok(game_after) => {
+            let player = game_after.players.iter().find(|p| p.id == 1).unwrap();
             let expected_shares = PlayerShares {
                 luxor: 2,
                 tower: 0,
@@ -239,12 +282,589 @@ fn buying_stocks_gives_player_bought_stocks() {
     }
 }
 
+#[test]
+fn share_price_follows_chain_size() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 1, 1, 1, 1, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let chain = [(5,3), (5,4), (5,5), (5,6), (5,7), (5,8)];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &chain);
+    let game = in_state(game, TurnState::BuyingOrDrawing);
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Luxor] };
+    match play_turn(&game, &action) {
+        Ok(game_after) => {
+            let player = game_after.players.iter().find(|p| p.id == 1).unwrap();
+            assert_eq!(player.money, 5400)
+        }
+        _ => {
+            panic!("Failed buying stocks")
+        }
+    }
+}
+
+#[test]
+fn chain_sizes_count_tiles_of_each_hotel() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 1, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = new_game_with_tiles(start_tiles, player_tiles);
+    let game = with_hotel(game, Hotel::Tower, &[(1,1), (1,2), (1,3)]);
+    let game = with_hotel(game, Hotel::Imperial, &[(5,8), (6,8)]);
+    let sizes = chain_sizes(&game);
+    assert_eq!(sizes.len(), 2);
+    assert_eq!(sizes.get(&Hotel::Tower), Some(&3));
+    assert_eq!(sizes.get(&Hotel::Imperial), Some(&2));
+}
+
+#[test]
+fn buying_stocks_fails_when_bank_is_out_of_shares() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let mut game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4)]);
+    game.bank_shares.luxor = 1;
+    game.turn_state = TurnState::BuyingOrDrawing;
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Luxor, Hotel::Luxor] };
+    match play_turn(&game, &action) {
+        Ok(_) => {
+            panic!("Buying stocks succeeded when the bank did not have enough shares")
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn buying_stocks_fails_if_player_does_not_have_turn() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4)]);
+    let game = in_state(game, TurnState::BuyingOrDrawing);
+    let action = Action::BuyStocks { player: 2, hotels: vec![Hotel::Luxor] };
+    match play_turn(&game, &action) {
+        Ok(_) => {
+            panic!("Buying stocks succeeded when player did not have turn")
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn buying_stocks_fails_before_placing_a_tile() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4)]);
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Luxor] };
+    match play_turn(&game, &action) {
+        Ok(_) => {
+            panic!("Buying stocks succeeded before placing a tile")
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn buying_stocks_fails_if_hotel_is_not_on_board() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4)]);
+    let game = in_state(game, TurnState::BuyingOrDrawing);
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Imperial] };
+    match play_turn(&game, &action) {
+        Ok(_) => {
+            panic!("Buying stocks succeeded for a hotel that is not on the board")
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn buying_stocks_fails_if_player_cannot_afford_them() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4)]);
+    let mut game = in_state(game, TurnState::BuyingOrDrawing);
+    game.players[0].money = 500;
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Luxor, Hotel::Luxor, Hotel::Luxor] };
+    match play_turn(&game, &action) {
+        Ok(_) => {
+            panic!("Buying stocks succeeded when player could not afford them")
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn player_cannot_buy_more_shares_than_allowed_per_turn() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,0), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,0), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4)]);
+    let game = in_state(game, TurnState::BuyingOrDrawing);
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Luxor; 4] };
+    assert_eq!(play_turn(&game, &action).err(), Some(GameError::TooManyShares { max: 3 }));
+}
+
+#[test]
+fn house_rules_change_cash_hand_size_and_prices() {
+    let config = GameConfig { starting_cash: 8000, hand_size: 5, cheap_price: 250, ..GameConfig::classic() };
+    let game = new_game_with_seed(config.clone(), 3, 1).unwrap();
+    assert_eq!(game.config, config);
+    for player in game.players.iter() {
+        assert_eq!(player.money, 8000);
+        assert_eq!(player.tiles.len(), 5);
+    }
+    let chain: Vec<Tile> = vec![Tile::new(8, 10, &game.config).unwrap(), Tile::new(8, 11, &game.config).unwrap()];
+    let mut game = in_state(with_turn(game, 1), TurnState::BuyingOrDrawing);
+    game.board = initial_board(&game.config, &chain);
+    let game = with_hotel(game, Hotel::Luxor, &[(8,10), (8,11)]);
+    let action = Action::BuyStocks { player: 1, hotels: vec![Hotel::Luxor] };
+    let game_after = play_turn(&game, &action).unwrap();
+    let buyer = game_after.players.iter().find(|p| p.id == 1).unwrap();
+    assert_eq!(buyer.money, 8000 - 250);
+}
+
+#[test]
+fn small_boards_deal_every_tile_once() {
+    let config = GameConfig { rows: 3, cols: 4, hand_size: 2, ..GameConfig::classic() };
+    let game = new_game_with_seed(config, 2, 5).unwrap();
+    assert_eq!((game.board.rows, game.board.cols), (3, 4));
+    assert_eq!(game.board.slots.len(), 12);
+    let mut tiles: Vec<Tile> = game.draw_pile.clone();
+    for player in game.players.iter() {
+        tiles.extend(player.tiles.iter().cloned());
+    }
+    for slot in game.board.slots.iter().filter(|s| s.has_tile) {
+        tiles.push(Tile::new(slot.row, slot.col, &game.config).unwrap());
+    }
+    let unique: HashSet<Tile> = tiles.iter().cloned().collect();
+    assert_eq!(unique.len(), 12);
+    assert_eq!(Tile::new(2, 3, &game.config).is_some(), true);
+    assert_eq!(Tile::new(3, 0, &game.config), None);
+    assert_eq!(Tile::new(0, 4, &game.config), None);
+}
+
+#[test]
+fn boards_too_small_for_the_players_are_rejected() {
+    let config = GameConfig { rows: 2, cols: 4, ..GameConfig::classic() };
+    assert_eq!(new_game(config, 2).err(), Some(GameError::InvalidBoardSize { rows: 2, cols: 4 }));
+    let config = GameConfig { rows: 27, ..GameConfig::classic() };
+    assert_eq!(new_game(config, 2).err(), Some(GameError::InvalidBoardSize { rows: 27, cols: 12 }));
+}
+
+#[test]
+fn configs_with_money_out_of_range_are_rejected() {
+    let config = GameConfig { price_step: i32::max_value(), ..GameConfig::classic() };
+    assert_eq!(new_lobby(config, 4, 42).err(), Some(GameError::InvalidConfig(format!("price_step must be between 0 and {}", MAX_PRICE))));
+    let config = GameConfig { starting_cash: -1, ..GameConfig::classic() };
+    assert!(new_lobby(config, 4, 42).is_err());
+    let config = GameConfig { majority_bonus: 1000000, ..GameConfig::classic() };
+    assert!(new_lobby(config, 4, 42).is_err());
+    let config = GameConfig { price_levels: (1..30).collect(), ..GameConfig::classic() };
+    assert!(new_lobby(config, 4, 42).is_err());
+    assert!(new_lobby(GameConfig::classic(), 4, 42).is_ok());
+}
+
+#[test]
+fn chains_on_large_boards_can_grow_past_255_tiles() {
+    let config = GameConfig { rows: 2, cols: 200, ..GameConfig::classic() };
+    let tiles: Vec<Tile> = (0..2).flat_map(|r| (0..200).map(move |c| Tile { row: r, col: c })).collect();
+    let mut board = initial_board(&config, &tiles);
+    board.tag(&tiles, &Hotel::Luxor);
+    assert_eq!(board.chain_sizes().get(&Hotel::Luxor), Some(&400));
+}
+
+#[test]
+fn board_finds_slots_neighbors_and_connected_groups() {
+    let start_tiles = [[1, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 1]];
+    let board = tiles_to_board(&start_tiles);
+    let corner = Tile { row: 8, col: 11 };
+    let slot = board.slot(&corner).unwrap();
+    assert_eq!((slot.row, slot.col, slot.has_tile), (8, 11, true));
+    assert!(board.slot(&Tile { row: 9, col: 0 }).is_none());
+    assert_eq!(board.neighbors(&corner), vec![Tile { row: 7, col: 11 }, Tile { row: 8, col: 10 }]);
+    assert_eq!(board.neighbors(&Tile { row: 4, col: 4 }).len(), 4);
+    let group: HashSet<Tile> = board.connected_group(&Tile { row: 0, col: 0 }).into_iter().collect();
+    let expected: HashSet<Tile> = vec![Tile { row: 0, col: 0 }, Tile { row: 0, col: 1 }, Tile { row: 1, col: 1 }].into_iter().collect();
+    assert_eq!(group, expected);
+    assert_eq!(board.connected_group(&Tile { row: 2, col: 3 }), vec![Tile { row: 2, col: 3 }]);
+}
+
+#[test]
+fn board_keeps_chain_sizes_up_to_date() {
+    let mut board = Board::new(9, 12);
+    let chain = vec![Tile { row: 0, col: 0 }, Tile { row: 0, col: 1 }];
+    board.place(&chain[0]);
+    board.tag(&chain, &Hotel::Luxor);
+    assert_eq!(board.chain_size(&Hotel::Luxor), 1);
+    board.place(&chain[1]);
+    assert_eq!(board.chain_size(&Hotel::Luxor), 2);
+    board.tag(&chain, &Hotel::Tower);
+    assert_eq!(board.chain_size(&Hotel::Tower), 2);
+    assert_eq!(board.chain_sizes().get(&Hotel::Luxor), None);
+}
+
 #[test]
 fn player_can_draw_tile() {
+    let game = in_state(with_turn(new_game_with_seed(GameConfig::classic(), 4, 42).unwrap(), 1), TurnState::BuyingOrDrawing);
+    let next_tile = game.draw_pile.last().cloned().unwrap();
+    let action = draw_action(&game, 1).unwrap();
+    match play_turn(&game, &action) {
+        Ok(game_after) => {
+            let player = game_after.players.iter().find(|p| p.id == 1).unwrap();
+            assert_eq!(player.tiles.len(), 7);
+            assert!(player.tiles.contains(&next_tile), "Player did not get the top tile of the bag");
+            assert_eq!(game_after.draw_pile.len(), game.draw_pile.len() - 1);
+        }
+        Err(e) => {
+            panic!("Drawing a tile failed: {}", e)
+        }
+    }
 }
 
 #[test]
 fn drawing_tile_ends_players_turn() {
+    let game = in_state(with_turn(new_game_with_seed(GameConfig::classic(), 4, 42).unwrap(), 1), TurnState::Drawing);
+    let action = draw_action(&game, 1).unwrap();
+    match play_turn(&game, &action) {
+        Ok(game_after) => {
+            assert_eq!(game_after.turn, 2);
+            assert_eq!(game_after.turn_state, TurnState::Placing);
+        }
+        Err(e) => {
+            panic!("Drawing a tile failed: {}", e)
+        }
+    }
+}
+
+#[test]
+fn drawing_replays_the_recorded_tile() {
+    let game = in_state(with_turn(new_game_with_seed(GameConfig::classic(), 4, 42).unwrap(), 1), TurnState::Drawing);
+    let recorded = game.draw_pile.last().cloned().unwrap();
+    let action = Action::DrawTile { player: 1, tile: recorded.clone() };
+    let game_after = play_turn(&game, &action).unwrap();
+    let player = game_after.players.iter().find(|p| p.id == 1).unwrap();
+    assert!(player.tiles.contains(&recorded), "Player did not get the recorded tile");
+    assert!(!game_after.draw_pile.contains(&recorded), "Recorded tile was still in the bag");
+}
+
+#[test]
+fn drawing_a_tile_not_in_the_bag_fails() {
+    let game = in_state(with_turn(new_game_with_seed(GameConfig::classic(), 4, 42).unwrap(), 1), TurnState::Drawing);
+    let in_hand = game.players[0].tiles[0].clone();
+    let action = Action::DrawTile { player: 1, tile: in_hand };
+    match play_turn(&game, &action) {
+        Ok(_) => {
+            panic!("Drawing a tile succeeded for a tile that was not in the bag")
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn drawing_a_tile_below_the_top_of_the_bag_fails() {
+    let game = in_state(with_turn(new_game_with_seed(GameConfig::classic(), 4, 42).unwrap(), 1), TurnState::Drawing);
+    let chosen = game.draw_pile[0].clone();
+    let action = Action::DrawTile { player: 1, tile: chosen.clone() };
+    assert_eq!(play_turn(&game, &action).err(), Some(GameError::TileNotInBag(chosen)));
+}
+
+#[test]
+fn turn_passes_without_a_draw_once_the_bag_is_empty() {
+    let game = in_state(with_turn(new_game_with_seed(GameConfig::classic(), 4, 42).unwrap(), 1), TurnState::BuyingOrDrawing);
+    assert_eq!(play_turn(&game, &Action::EndTurn { player: 1 }).err(), Some(GameError::MustDrawTile));
+    let game = Game { draw_pile: Vec::new(), ..game };
+    let action = draw_action(&game, 1).unwrap();
+    assert_eq!(action_player(&action), 1);
+    let game_after = play_turn(&game, &action).unwrap();
+    assert_eq!(game_after.turn, 2);
+    assert_eq!(game_after.turn_state, TurnState::Placing);
+    assert_eq!(game_after.players[0].tiles, game.players[0].tiles);
+    let tile = game_after.players[1].tiles[0].clone();
+    assert!(play_turn(&game_after, &Action::PlaceTile { player: 2, tile: tile }).is_ok());
+}
+
+#[test]
+fn editions_without_encoded_rules_are_refused() {
+    assert_eq!(GameConfig::edition("classic"), Ok(GameConfig::classic()));
+    assert!(GameConfig::edition("1999").is_err());
+    assert!(GameConfig::edition("2008").is_err());
+    assert!(GameConfig::edition("deluxe").is_err());
+}
+
+#[test]
+fn player_views_only_show_own_tiles() {
+    let game = new_game_with_seed(GameConfig::classic(), 4, 42).unwrap();
+    let view = game_view(&game, Some(2));
+    assert_eq!(view.viewer, Some(2));
+    assert_eq!(view.tiles_in_bag, game.draw_pile.len());
+    for (player, seen) in game.players.iter().zip(view.players.iter()) {
+        assert_eq!(seen.id, player.id);
+        assert_eq!(seen.hand_size, 6);
+        if player.id == 2 {
+            assert_eq!(seen.tiles, player.tiles);
+        } else {
+            assert!(seen.tiles.is_empty(), "Player 2 can see the tiles of player {}", player.id);
+        }
+    }
+    let spectator = game_view(&game, None);
+    assert!(spectator.players.iter().all(|p| p.tiles.is_empty()));
+
+    let blocked = BlockedPlacement {
+        player: 2,
+        tiles: game.players[1].tiles.iter().map(|t| BlockedTile { tile: t.clone(), reason: Unplayable::MergesSafeChains }).collect()
+    };
+    let game = Game { blocked_placement: Some(blocked.clone()), ..game };
+    assert_eq!(game_view(&game, Some(2)).blocked_placement, Some(blocked));
+    let hidden = BlockedPlacement { player: 2, tiles: Vec::new() };
+    assert_eq!(game_view(&game, Some(3)).blocked_placement, Some(hidden.clone()));
+    assert_eq!(game_view(&game, None).blocked_placement, Some(hidden));
+}
+
+#[test]
+fn drawn_tiles_are_only_shown_to_the_drawing_player() {
+    let config = GameConfig::classic();
+    let draw = Action::DrawTile { player: 2, tile: Tile::new(0, 0, &config).unwrap() };
+    assert!(action_view(&draw, Some(2)).is_some());
+    assert!(action_view(&draw, Some(3)).is_none());
+    assert!(action_view(&draw, None).is_none());
+    let place = Action::PlaceTile { player: 2, tile: Tile::new(0, 0, &config).unwrap() };
+    assert!(action_view(&place, None).is_some());
+}
+
+#[test]
+fn host_starts_the_game_once_everyone_is_ready() {
+    let lobby = new_lobby(GameConfig::classic(), 4, 42).unwrap();
+    assert_eq!(lobby.turn_state, TurnState::WaitingForPlayers);
+    let join_ann = join_action(&lobby, "Ann").unwrap();
+    let game = play_turn(&lobby, &join_ann).unwrap();
+    let join_bob = join_action(&game, "Bob").unwrap();
+    let game = play_turn(&game, &join_bob).unwrap();
+    assert_eq!(game.host, Some(1));
+    let names: Vec<String> = game.players.iter().map(|p| p.name.clone()).collect();
+    assert_eq!(names, vec!["Ann".to_string(), "Bob".to_string()]);
+    assert_eq!(play_turn(&game, &Action::StartGame { player: 1 }).err(), Some(GameError::PlayersNotReady));
+    let actions = vec![
+        Action::Ready { player: 1, ready: true },
+        Action::Ready { player: 2, ready: true }
+    ];
+    let game = compute_state(&game, &actions).unwrap();
+    assert_eq!(play_turn(&game, &Action::StartGame { player: 2 }).err(), Some(GameError::NotHost(2)));
+    let game = play_turn(&game, &Action::StartGame { player: 1 }).unwrap();
+    assert_eq!(game.turn_state, TurnState::Placing);
+    assert_eq!(game.starting_draw.len(), 2);
+    assert!(game.players.iter().all(|p| p.tiles.len() == 6));
+    assert_eq!(play_turn(&game, &Action::Leave { player: 2 }).err(), Some(GameError::WrongPhase(TurnState::Placing)));
+}
+
+#[test]
+fn lobby_seats_fill_up_and_host_moves_on_when_leaving() {
+    let lobby = new_lobby(GameConfig::classic(), 2, 1).unwrap();
+    let actions = vec![
+        Action::Join { player: 1, name: "Ann".to_string() },
+        Action::Join { player: 2, name: "Bob".to_string() }
+    ];
+    let game = compute_state(&lobby, &actions).unwrap();
+    assert_eq!(join_action(&game, "Cid").err(), Some(GameError::GameFull));
+    let taken = Action::Join { player: 2, name: "Cid".to_string() };
+    assert_eq!(play_turn(&game, &taken).err(), Some(GameError::SeatTaken(2)));
+    let game = play_turn(&game, &Action::Leave { player: 1 }).unwrap();
+    assert_eq!(game.host, Some(2));
+    let game = play_turn(&game, &Action::Ready { player: 2, ready: true }).unwrap();
+    assert_eq!(play_turn(&game, &Action::StartGame { player: 2 }).err(), Some(GameError::NotEnoughPlayers(1)));
+    match join_action(&game, "Cid") {
+        Ok(Action::Join { player, .. }) => assert_eq!(player, 1),
+        other => panic!("Expected to join in the free seat, got {:?}", other)
+    }
+}
+
+#[test]
+fn journals_replay_games_and_drop_a_line_cut_short() {
+    use std::fs;
+    use std::fs::File;
+    use std::io::{Read, Write};
+    let dir = unique_temp_dir("aqueren-journal-test");
+    let lobby = new_lobby(GameConfig::classic(), 4, 42).unwrap();
+    let mut journal = journal::Journal::create(&dir, 1, &lobby).unwrap();
+    journal.append(&Action::Join { player: 1, name: "Ann".to_string() }, Some("secret")).unwrap();
+    journal.append(&Action::Ready { player: 1, ready: true }, None).unwrap();
+
+    let (recorded, _) = journal::load(&dir, 1, false).unwrap();
+    assert_eq!(recorded.actions[0].1, Some("secret".to_string()));
+    let actions = recorded.actions.into_iter().map(|(action, _)| action).collect();
+    let game = compute_state(&recorded.initial_game, &actions).unwrap();
+    assert_eq!(game.players[0].name, "Ann");
+    assert!(game.players[0].ready);
+    assert_eq!(journal::journal_ids(&dir).unwrap(), vec![1]);
+
+    let path = dir.join("1.journal");
+    let mut contents = String::new();
+    File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+    File::create(&path).unwrap().write_all(contents[..contents.len() - 5].as_bytes()).unwrap();
+    let (recorded, mut journal) = journal::load(&dir, 1, false).unwrap();
+    assert_eq!(recorded.cut_short, Some(3));
+    assert_eq!(recorded.actions.len(), 1);
+    assert!(!recorded.game.players[0].ready);
+
+    journal.append(&Action::Ready { player: 1, ready: true }, None).unwrap();
+    let (recorded, _) = journal::load(&dir, 1, false).unwrap();
+    assert_eq!(recorded.cut_short, None);
+    assert_eq!(recorded.actions.len(), 2);
+    assert!(recorded.game.players[0].ready);
+
+    File::create(&path).unwrap().write_all(contents[..10].as_bytes()).unwrap();
+    match journal::load(&dir, 1, false) {
+        Err(journal::JournalError::Truncated { line }) => assert_eq!(line, 1),
+        _ => panic!("Expected a journal without a whole header to be reported as truncated")
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn snapshots_spare_replaying_and_are_checked_against_the_journal() {
+    use std::fs;
+    let dir = unique_temp_dir("aqueren-snapshot-test");
+    let lobby = new_lobby(GameConfig::classic(), 4, 42).unwrap();
+    let actions = vec![
+        Action::Join { player: 1, name: "Ann".to_string() },
+        Action::Join { player: 2, name: "Bob".to_string() },
+        Action::Ready { player: 1, ready: true }
+    ];
+    let mut journal = journal::Journal::create(&dir, 1, &lobby).unwrap();
+    journal.append(&actions[0], Some("ann")).unwrap();
+    journal.append(&actions[1], Some("bob")).unwrap();
+    journal.snapshot(&compute_state(&lobby, &actions[..2].to_vec()).unwrap()).unwrap();
+    journal.append(&actions[2], None).unwrap();
+
+    let (recorded, _) = journal::load(&dir, 1, true).unwrap();
+    assert_eq!(recorded.replayed, 1);
+    assert!(recorded.game == compute_state(&lobby, &actions).unwrap());
+
+    journal.snapshot(&compute_state(&lobby, &actions[..1].to_vec()).unwrap()).unwrap();
+    assert_eq!(journal::load(&dir, 1, false).unwrap().0.game.players.len(), 1);
+    match journal::load(&dir, 1, true) {
+        Err(journal::JournalError::SnapshotMismatch { seq }) => assert_eq!(seq, 3),
+        _ => panic!("Expected the snapshot to disagree with the journal")
+    }
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn tile_bag_has_every_tile_once() {
+    let game = new_game_with_seed(GameConfig::classic(), 4, 42).unwrap();
+    let mut tiles: Vec<Tile> = game.draw_pile.clone();
+    for player in game.players.iter() {
+        tiles.extend(player.tiles.iter().cloned());
+    }
+    for slot in game.board.slots.iter().filter(|s| s.has_tile) {
+        tiles.push(Tile::new(slot.row, slot.col, &game.config).unwrap());
+    }
+    let unique: HashSet<Tile> = tiles.iter().cloned().collect();
+    assert_eq!(tiles.len(), 108);
+    assert_eq!(unique.len(), 108);
+}
+
+#[test]
+fn games_with_the_same_seed_are_identical() {
+    let first = new_game_with_seed(GameConfig::classic(), 4, 7).unwrap();
+    let second = new_game_with_seed(GameConfig::classic(), 4, 7).unwrap();
+    assert_eq!(first.draw_pile, second.draw_pile);
+    for (a, b) in first.players.iter().zip(second.players.iter()) {
+        assert_eq!(a.tiles, b.tiles);
+    }
+    let other = new_game_with_seed(GameConfig::classic(), 4, 8).unwrap();
+    assert!(first.draw_pile != other.draw_pile, "Different seeds gave the same tile bag");
 }
 
 #[test]
@@ -263,10 +883,10 @@ fn placing_an_adjacent_tile_creates_chain() {
     [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
     [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
     let game = new_game_with_tiles(start_tiles, player_tiles);
-    let tile_to_place = Tile::new(0,5).unwrap();
-    let action = Action::PlaceTile { player: PlayerId::One, tile: tile_to_place.clone() };
+    let tile_to_place = Tile::new(0, 5, &game.config).unwrap();
+    let action = Action::PlaceTile { player: 1, tile: tile_to_place.clone() };
     match play_turn(&game, &action) {
-        TurnResult::Success(game_after) => {
+        Ok(game_after) => {
             let game_state = game_after.turn_state;
             assert!(game_state == TurnState::CreatingChain, "Placing adjacent tile did not change state to creating chain")
         }
@@ -292,10 +912,10 @@ fn placing_a_tile_goes_to_buy_and_draw() {
     [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
     [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
     let game = new_game_with_tiles(start_tiles, player_tiles);
-    let tile_to_place = Tile::new(0,2).unwrap();
-    let action = Action::PlaceTile { player: PlayerId::One, tile: tile_to_place.clone() };
+    let tile_to_place = Tile::new(0, 2, &game.config).unwrap();
+    let action = Action::PlaceTile { player: 1, tile: tile_to_place.clone() };
     match play_turn(&game, &action) {
-        TurnResult::Success(game_after) => {
+        Ok(game_after) => {
             let game_state = game_after.turn_state;
             assert!(game_state == TurnState::BuyingOrDrawing, "Placing a tile did not change state to buying or drawing")
         }
@@ -305,34 +925,566 @@ fn placing_a_tile_goes_to_buy_and_draw() {
     }
 }
 
+#[test]
+fn founding_a_chain_tags_connected_tiles() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = new_game_with_tiles(start_tiles, player_tiles);
+    let actions = vec![
+        Action::PlaceTile { player: 1, tile: Tile::new(0, 5, &game.config).unwrap() },
+        Action::FoundChain { player: 1, hotel: Hotel::Festival }
+    ];
+    match compute_state(&game, &actions) {
+        Ok(game_after) => {
+            assert_eq!(game_after.turn_state, TurnState::BuyingOrDrawing);
+            assert_eq!(chain_sizes(&game_after).get(&Hotel::Festival), Some(&3));
+            let player = game_after.players.iter().find(|p| p.id == 1).unwrap();
+            assert_eq!(player.shares.festival, 1);
+        }
+        Err(e) => {
+            panic!("Founding a chain failed: {}", e)
+        }
+    }
+}
+
+#[test]
+fn founding_a_chain_fails_if_chain_is_on_board() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Festival, &[(5,3), (5,4)]);
+    let actions = vec![
+        Action::PlaceTile { player: 1, tile: Tile::new(0, 5, &game.config).unwrap() },
+        Action::FoundChain { player: 1, hotel: Hotel::Festival }
+    ];
+    match compute_state(&game, &actions) {
+        Ok(_) => {
+            panic!("Founding a chain succeeded when chain was already on the board")
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn placing_a_tile_next_to_a_chain_grows_chain() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [1, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (5,4) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Tower, &[(6,3), (6,4)]);
+    let action = Action::PlaceTile { player: 1, tile: Tile::new(5, 4, &game.config).unwrap() };
+    match play_turn(&game, &action) {
+        Ok(game_after) => {
+            assert_eq!(game_after.turn_state, TurnState::BuyingOrDrawing);
+            assert_eq!(chain_sizes(&game_after).get(&Hotel::Tower), Some(&3));
+        }
+        _ => {
+            panic!("Placing a valid tile failed")
+        }
+    }
+}
+
+#[test]
+fn merging_pays_bonuses_and_absorbs_smaller_chain() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (5,3) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = new_game_with_tiles(start_tiles, player_tiles);
+    let game = with_hotel(game, Hotel::Luxor, &[(4,2), (4,3)]);
+    let game = with_hotel(game, Hotel::Tower, &[(6,3), (6,4), (6,5)]);
+    let game = with_shares(game, 1, PlayerShares { luxor: 2, ..no_shares() });
+    let game = with_shares(game, 2, PlayerShares { luxor: 1, ..no_shares() });
+    let actions = vec![
+        Action::PlaceTile { player: 1, tile: Tile::new(5, 3, &game.config).unwrap() },
+        Action::HandleMergeStocks { player: 1, hold: 0, sell: 2, trade: 0 },
+        Action::HandleMergeStocks { player: 2, hold: 1, sell: 0, trade: 0 }
+    ];
+    match compute_state(&game, &actions) {
+        Ok(game_after) => {
+            assert_eq!(game_after.turn_state, TurnState::BuyingOrDrawing);
+            let sizes = chain_sizes(&game_after);
+            assert_eq!(sizes.get(&Hotel::Tower), Some(&6));
+            assert_eq!(sizes.get(&Hotel::Luxor), None);
+            let one = game_after.players.iter().find(|p| p.id == 1).unwrap();
+            let two = game_after.players.iter().find(|p| p.id == 2).unwrap();
+            assert_eq!(one.money, 6000 + 2000 + 400);
+            assert_eq!(one.shares.luxor, 0);
+            assert_eq!(two.money, 6000 + 1000);
+            assert_eq!(two.shares.luxor, 1);
+            assert_eq!(game_after.bank_shares.luxor, game.bank_shares.luxor + 2);
+        }
+        Err(e) => {
+            panic!("Merging failed: {}", e)
+        }
+    }
+}
+
+#[test]
+fn bonuses_pay_majority_and_minority_holders() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,0), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,0), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4), (5,5)]);
+    let game = with_shares(game, 1, PlayerShares { luxor: 4, ..no_shares() });
+    let game = with_shares(game, 2, PlayerShares { luxor: 2, ..no_shares() });
+    let game = with_shares(game, 3, PlayerShares { luxor: 1, ..no_shares() });
+    assert_eq!(bonuses(&game, Hotel::Luxor), vec![(1, 3000), (2, 1500)]);
+    assert_eq!(bonuses(&game, Hotel::Tower), vec![]);
+    let sole = with_shares(new_game_with_tiles(start_tiles, player_tiles), 4, PlayerShares { luxor: 1, ..no_shares() });
+    let sole = with_hotel(sole, Hotel::Luxor, &[(5,3), (5,4), (5,5)]);
+    assert_eq!(bonuses(&sole, Hotel::Luxor), vec![(4, 4500)]);
+}
+
+#[test]
+fn tied_bonuses_are_split_and_rounded_up_to_the_nearest_hundred() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,0), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,0), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Luxor, &[(5,3), (5,4), (5,5)]);
+    let majority_tie = with_shares(game.clone(), 1, PlayerShares { luxor: 3, ..no_shares() });
+    let majority_tie = with_shares(majority_tie, 2, PlayerShares { luxor: 3, ..no_shares() });
+    let majority_tie = with_shares(majority_tie, 3, PlayerShares { luxor: 1, ..no_shares() });
+    assert_eq!(bonuses(&majority_tie, Hotel::Luxor), vec![(1, 2300), (2, 2300)]);
+    let minority_tie = with_shares(game, 1, PlayerShares { luxor: 5, ..no_shares() });
+    let minority_tie = with_shares(minority_tie, 2, PlayerShares { luxor: 2, ..no_shares() });
+    let minority_tie = with_shares(minority_tie, 4, PlayerShares { luxor: 2, ..no_shares() });
+    assert_eq!(bonuses(&minority_tie, Hotel::Luxor), vec![(1, 3000), (2, 800), (4, 800)]);
+}
+
+#[test]
+fn merging_equal_chains_lets_mergemaker_choose_survivor() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (5,3) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = new_game_with_tiles(start_tiles, player_tiles);
+    let game = with_hotel(game, Hotel::Luxor, &[(4,2), (4,3)]);
+    let game = with_hotel(game, Hotel::Tower, &[(6,3), (6,4)]);
+    let place = Action::PlaceTile { player: 1, tile: Tile::new(5, 3, &game.config).unwrap() };
+    let game_after_place = play_turn(&game, &place).unwrap();
+    assert_eq!(game_after_place.turn_state, TurnState::ChoosingSurvivor);
+    let choose = Action::ChooseSurvivor { player: 1, hotel: Hotel::Luxor };
+    match play_turn(&game_after_place, &choose) {
+        Ok(game_after) => {
+            assert_eq!(game_after.turn_state, TurnState::BuyingOrDrawing);
+            assert_eq!(chain_sizes(&game_after).get(&Hotel::Luxor), Some(&5));
+            assert_eq!(chain_sizes(&game_after).get(&Hotel::Tower), None);
+        }
+        Err(e) => {
+            panic!("Choosing a survivor failed: {}", e)
+        }
+    }
+}
+
+#[test]
+fn merging_several_chains_resolves_largest_defunct_first() {
+    let start_tiles = [[0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 1, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (4,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,6) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,7) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,7) ]];
+    let game = new_game_with_tiles(start_tiles, player_tiles);
+    let game = with_hotel(game, Hotel::Tower, &[(0,5), (1,5), (2,5), (2,6), (3,5)]);
+    let game = with_hotel(game, Hotel::Luxor, &[(4,2), (4,3), (4,4)]);
+    let game = with_hotel(game, Hotel::American, &[(5,5), (6,5)]);
+    let game = with_shares(game, 3, PlayerShares { american: 2, ..no_shares() });
+    let place = Action::PlaceTile { player: 1, tile: Tile::new(4, 5, &game.config).unwrap() };
+    let game_after = play_turn(&game, &place).unwrap();
+    assert_eq!(game_after.turn_state, TurnState::Merging);
+    let merger = game_after.merger.clone().unwrap();
+    assert_eq!(merger.survivor, Some(Hotel::Tower));
+    assert_eq!(merger.defunct, vec![Hotel::American]);
+    assert_eq!(merger.shareholders, vec![3]);
+    assert_eq!(chain_sizes(&game_after).get(&Hotel::Tower), Some(&8));
+    let trade = Action::HandleMergeStocks { player: 3, hold: 0, sell: 0, trade: 2 };
+    let game_after = play_turn(&game_after, &trade).unwrap();
+    let three = game_after.players.iter().find(|p| p.id == 3).unwrap();
+    assert_eq!(three.shares.american, 0);
+    assert_eq!(three.shares.tower, 1);
+    assert_eq!(chain_sizes(&game_after).get(&Hotel::Tower), Some(&11));
+    assert_eq!(game_after.turn_state, TurnState::BuyingOrDrawing);
+}
+
+#[test]
+fn merging_equal_defunct_chains_lets_mergemaker_choose_the_order() {
+    let start_tiles = [[0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (4,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,6) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,7) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,7) ]];
+    let game = new_game_with_tiles(start_tiles, player_tiles);
+    let game = with_hotel(game, Hotel::Tower, &[(0,5), (1,5), (2,5), (3,5)]);
+    let game = with_hotel(game, Hotel::Luxor, &[(4,3), (4,4)]);
+    let game = with_hotel(game, Hotel::American, &[(5,5), (6,5)]);
+    let game = with_shares(game, 3, PlayerShares { american: 2, ..no_shares() });
+    let place = Action::PlaceTile { player: 1, tile: Tile::new(4, 5, &game.config).unwrap() };
+    let game_after = play_turn(&game, &place).unwrap();
+    assert_eq!(game_after.turn_state, TurnState::ChoosingDefunct);
+    let survivor = Action::ChooseDefunct { player: 1, hotel: Hotel::Tower };
+    assert_eq!(play_turn(&game_after, &survivor).err(), Some(GameError::NotLargestChain(Hotel::Tower)));
+    let choose = Action::ChooseDefunct { player: 1, hotel: Hotel::American };
+    let game_after = play_turn(&game_after, &choose).unwrap();
+    assert_eq!(game_after.turn_state, TurnState::Merging);
+    let merger = game_after.merger.clone().unwrap();
+    assert_eq!(merger.defunct, vec![Hotel::American, Hotel::Luxor]);
+    assert_eq!(merger.shareholders, vec![3]);
+    let sell = Action::HandleMergeStocks { player: 3, hold: 0, sell: 2, trade: 0 };
+    let game_after = play_turn(&game_after, &sell).unwrap();
+    assert_eq!(game_after.turn_state, TurnState::BuyingOrDrawing);
+    assert_eq!(chain_sizes(&game_after).get(&Hotel::Tower), Some(&9));
+}
+
+#[test]
+fn ending_the_game_fails_before_end_condition() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 1, 1, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Tower, &[(5,3), (5,4), (5,5)]);
+    let action = Action::EndGame { player: 1 };
+    match play_turn(&game, &action) {
+        Ok(_) => {
+            panic!("Ending the game succeeded before any chain was safe")
+        }
+        _ => {}
+    }
+}
+
+#[test]
+fn ending_the_game_pays_bonuses_and_ranks_players() {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (0,0), (0,1), (0,2), (0,3), (0,4), (0,5) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (3,3), (3,1), (3,2), (3,3), (3,4), (3,5) ]];
+    let chain = [(5,1), (5,2), (5,3), (5,4), (5,5), (5,6), (5,7), (5,8), (5,9), (5,10), (5,11)];
+    let game = with_hotel(new_game_with_tiles(start_tiles, player_tiles), Hotel::Tower, &chain);
+    let game = with_shares(game, 1, PlayerShares { tower: 3, ..no_shares() });
+    let game = with_shares(game, 2, PlayerShares { tower: 1, ..no_shares() });
+    let action = Action::EndGame { player: 1 };
+    match play_turn(&game, &action) {
+        Ok(game_after) => {
+            assert_eq!(game_after.turn_state, TurnState::GameOver);
+            let standings = game_after.final_score.unwrap().standings;
+            assert_eq!(standings[0], Standing { rank: 1, player: 1, money: 6000 + 7000 + 2100 });
+            assert_eq!(standings[1], Standing { rank: 2, player: 2, money: 6000 + 3500 + 700 });
+            assert_eq!(standings[2].rank, 3);
+            assert_eq!(standings[3].rank, 3);
+        }
+        Err(e) => {
+            panic!("Ending the game failed: {}", e)
+        }
+    }
+}
+
+#[test]
+fn placing_a_tile_between_safe_chains_fails() {
+    let game = new_game_with_safe_chains();
+    let dead_tile = Tile::new(4, 0, &game.config).unwrap();
+    assert!(!legal_tiles(&game, 1).contains(&dead_tile), "Dead tile was listed as legal");
+    let action = Action::PlaceTile { player: 1, tile: dead_tile.clone() };
+    match play_turn(&game, &action) {
+        Err(GameError::IllegalTile(tile, Unplayable::MergesSafeChains)) => {
+            assert_eq!(tile, dead_tile)
+        }
+        _ => {
+            panic!("Placing a tile merging two safe chains did not fail as illegal")
+        }
+    }
+}
+
+#[test]
+fn placing_a_tile_founding_an_eighth_chain_fails() {
+    let start_tiles = [[1, 1, 0, 1, 1, 0, 1, 1, 0, 1, 1, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [1, 1, 0, 1, 1, 0, 1, 1, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (6,0), (6,1), (6,2), (6,3), (6,4), (4,6) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (3,2), (3,1), (3,2), (3,3), (3,4), (3,5) ],
+    [ (7,3), (7,1), (7,2), (7,3), (7,4), (7,5) ]];
+    let game = new_game_with_tiles(start_tiles, player_tiles);
+    let game = with_hotel(game, Hotel::Luxor, &[(0,0), (0,1)]);
+    let game = with_hotel(game, Hotel::Tower, &[(0,3), (0,4)]);
+    let game = with_hotel(game, Hotel::American, &[(0,6), (0,7)]);
+    let game = with_hotel(game, Hotel::Festival, &[(0,9), (0,10)]);
+    let game = with_hotel(game, Hotel::Worldwide, &[(2,0), (2,1)]);
+    let game = with_hotel(game, Hotel::Continental, &[(2,3), (2,4)]);
+    let game = with_hotel(game, Hotel::Imperial, &[(2,6), (2,7)]);
+    let action = Action::PlaceTile { player: 1, tile: Tile::new(4, 6, &game.config).unwrap() };
+    match play_turn(&game, &action) {
+        Err(GameError::IllegalTile(_, Unplayable::NoChainAvailable)) => {}
+        _ => {
+            panic!("Placing a tile founding an eighth chain did not fail as illegal")
+        }
+    }
+}
+
+#[test]
+fn player_can_replace_dead_tile() {
+    let game = in_state(new_game_with_safe_chains(), TurnState::BuyingOrDrawing);
+    let dead_tile = Tile::new(4, 0, &game.config).unwrap();
+    let action = replace_dead_tile_action(&game, 1, dead_tile.clone()).unwrap();
+    match play_turn(&game, &action) {
+        Ok(game_after) => {
+            let player = game_after.players.iter().find(|p| p.id == 1).unwrap();
+            assert_eq!(player.tiles.len(), 6);
+            assert!(!player.tiles.contains(&dead_tile), "Dead tile was still on player");
+            assert_eq!(game_after.draw_pile.len(), game.draw_pile.len() - 1);
+        }
+        Err(e) => {
+            panic!("Replacing a dead tile failed: {}", e)
+        }
+    }
+}
+
+#[test]
+fn player_cannot_replace_playable_tile() {
+    let game = in_state(new_game_with_safe_chains(), TurnState::BuyingOrDrawing);
+    let action = replace_dead_tile_action(&game, 1, Tile::new(8, 0, &game.config).unwrap()).unwrap();
+    match play_turn(&game, &action) {
+        Err(GameError::TileNotDead(_)) => {}
+        _ => {
+            panic!("Replacing a playable tile did not fail")
+        }
+    }
+}
+
+#[test]
+fn player_without_legal_tiles_can_pass_placement() {
+    let mut game = new_game_with_safe_chains();
+    game.players[0].tiles = (0..6).map(|c| Tile::new(4, c, &game.config).unwrap()).collect();
+    game.turn = 4;
+    game.turn_state = TurnState::Drawing;
+    let draw = draw_action(&game, 4).unwrap();
+    let game_after_draw = play_turn(&game, &draw).unwrap();
+    let blocked = game_after_draw.blocked_placement.clone().unwrap();
+    assert_eq!(blocked.player, 1);
+    assert_eq!(blocked.tiles.len(), 6);
+    assert!(blocked.tiles.iter().all(|t| t.reason == Unplayable::MergesSafeChains));
+    let pass = Action::PassPlacement { player: 1 };
+    match play_turn(&game_after_draw, &pass) {
+        Ok(game_after) => {
+            assert_eq!(game_after.turn_state, TurnState::BuyingOrDrawing);
+            assert_eq!(game_after.blocked_placement, None);
+        }
+        Err(e) => {
+            panic!("Passing placement failed: {}", e)
+        }
+    }
+}
+
+#[test]
+fn passing_placement_fails_with_a_legal_tile() {
+    let game = new_game_with_safe_chains();
+    let action = Action::PassPlacement { player: 1 };
+    match play_turn(&game, &action) {
+        Err(GameError::MustPlaceTile) => {}
+        _ => {
+            panic!("Passing placement did not fail when a tile could be placed")
+        }
+    }
+}
+
 fn new_game_with_tiles(start_tiles: BoardTiles, player_tiles: PlayerTiles) -> Game {
-    let (starting_tiles, _) = board_tiles_to_tiles(&start_tiles);
-    let players = player_tiles
+    let config = GameConfig::classic();
+    let (starting_tiles, other_tiles) = board_tiles_to_tiles(&start_tiles);
+    let players: Vec<Player> = player_tiles
         .iter()
         .enumerate()
         .map(|(i, tiles)| {
             let _tiles = tiles
                 .iter()
-                .map(|&(r,c)| Tile::new(r as u8, c as u8).unwrap() )
+                .map(|&(r,c)| Tile::new(r as u8, c as u8, &config).unwrap() )
                 .collect();
-            new_player(PlayerId::new((i+1) as u8).unwrap(), _tiles)
+            new_player(&config, (i + 1) as PlayerId, _tiles)
         })
     .collect();
-    let slots = initial_slots(starting_tiles);
+    let draw_pile = other_tiles
+        .into_iter()
+        .filter(|t| !players.iter().any(|p| p.tiles.contains(t)))
+        .collect();
+    let board = initial_board(&config, &starting_tiles);
     Game {
-        board: Board { slots: slots },
+        bank_shares: full_bank(&config),
+        board: board,
+        config: config,
+        seed: 0,
+        seats: 4,
+        host: Some(1),
         players: players,
-        turn: PlayerId::One,
-        turn_state: TurnState::Placing
+        draw_pile: draw_pile,
+        starting_draw: Vec::new(),
+        turn: 1,
+        turn_state: TurnState::Placing,
+        placed_tile: None,
+        blocked_placement: None,
+        merger: None,
+        final_score: None
     }
 }
 
+fn new_game_with_safe_chains() -> Game {
+    let start_tiles = [[0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0],
+        [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0]];
+    let player_tiles = [[ (4,0), (8,0), (8,1), (8,2), (8,3), (8,4) ],
+    [ (1,0), (1,1), (1,2), (1,3), (1,4), (1,5) ],
+    [ (2,2), (2,1), (2,2), (2,3), (2,4), (2,5) ],
+    [ (7,3), (7,1), (7,2), (7,3), (7,4), (7,5) ]];
+    let tower: Vec<(u8, u8)> = (0..11).map(|c| (3, c)).collect();
+    let luxor: Vec<(u8, u8)> = (0..11).map(|c| (5, c)).collect();
+    let game = new_game_with_tiles(start_tiles, player_tiles);
+    let game = with_hotel(game, Hotel::Tower, &tower);
+    with_hotel(game, Hotel::Luxor, &luxor)
+}
+
+fn with_hotel(mut game: Game, hotel: Hotel, tiles: &[(u8, u8)]) -> Game {
+    let tiles: Vec<Tile> = tiles.iter().map(|&(r, c)| Tile { row: r, col: c }).collect();
+    game.board.tag(&tiles, &hotel);
+    game
+}
+
+fn with_turn(mut game: Game, player: PlayerId) -> Game {
+    game.turn = player;
+    game
+}
+
+fn in_state(mut game: Game, turn_state: TurnState) -> Game {
+    game.turn_state = turn_state;
+    game
+}
+
+fn with_shares(mut game: Game, player: PlayerId, shares: PlayerShares) -> Game {
+    for p in game.players.iter_mut().filter(|p| p.id == player) {
+        p.shares = shares.clone();
+    }
+    game
+}
+
+fn no_shares() -> PlayerShares {
+    PlayerShares { luxor: 0, tower: 0, american: 0, festival: 0, worldwide: 0, continental: 0, imperial: 0 }
+}
+
 fn board_tiles_to_tiles(tiles: &BoardTiles) -> (Vec<Tile>, Vec<Tile>) {
     let mut chosen = Vec::new();
     let mut others = Vec::new();
     for row in 0..tiles.len() {
         for col in 0..tiles[0].len() {
-            let tile = Tile::new(row as u8, col as u8).unwrap();
+            let tile = Tile::new(row as u8, col as u8, &GameConfig::classic()).unwrap();
             if tiles[row][col] == 1 {
                 chosen.push(tile)
             } else {
@@ -353,26 +1505,24 @@ fn assert_boards_equal(expected: &Board, actual: &Board) {
 }
 
 fn tiles_to_board(tiles: &BoardTiles) -> Board {
-    let board_tiles = tiles
-        .iter()
-        .enumerate()
-        .flat_map(|(row, row_tiles)| {
-            let slots: Vec<Slot> = row_tiles
-                .iter()
-                .enumerate()
-                .map(|(col, val): (usize, &i32)| {
-                    let has_tile = *val == 1;
-                    Slot { row: row as u8, col: col as u8, has_tile: has_tile, hotel: None }
-                })
-            .collect();
-            slots
-        }).collect();
-    Board { slots: board_tiles }
+    let mut board = Board::new(tiles.len() as u8, tiles[0].len() as u8);
+    for (row, row_tiles) in tiles.iter().enumerate() {
+        for (col, val) in row_tiles.iter().enumerate() {
+            if *val == 1 {
+                board.place(&Tile { row: row as u8, col: col as u8 });
+            }
+        }
+    }
+    board
 }
 
-fn row_to_char<'a>(row: u8) -> &'a str {
-    let mapping = ["A", "B", "C", "D", "E", "F", "G", "H", "I"];
-    mapping[row as usize]
+fn row_to_char(row: u8) -> char {
+    (b'A' + row) as char
+}
+
+fn print_column_labels(cols: u8) -> String {
+    let labels: Vec<String> = (1..cols as u16 + 1).map(|c| format!("{:<2}", c)).collect();
+    format!("   {}", labels.join(" "))
 }
 
 fn print_tile(slot: &Slot) -> String{
@@ -381,8 +1531,8 @@ fn print_tile(slot: &Slot) -> String{
 
 fn print_board(board: &Board) -> String {
     let mut string = String::new();
-    string.push_str("   1  2  3  4  5  6  7  8  9  10 11 12\n");
-    for row in board.slots.chunks(COLS as usize) {
+    string.push_str(&format!("{}\n", print_column_labels(board.cols)));
+    for row in board.slots.chunks(board.cols as usize) {
         let row_char = row_to_char(row[0].row);
         string.push_str(&format!("{}  ", row_char));
         for slot in row {
@@ -390,6 +1540,13 @@ fn print_board(board: &Board) -> String {
         }
         string.push_str(&format!("{}\n", row_char));
     }
-    string.push_str("   1  2  3  4  5  6  7  8  9  10 11 12");
+    string.push_str(&print_column_labels(board.cols));
     string
 }
+
+/// A fresh directory path under the system temp dir, so concurrent test runs keep apart.
+fn unique_temp_dir(name: &str) -> std::path::PathBuf {
+    use std::time::{SystemTime, UNIX_EPOCH};
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    std::env::temp_dir().join(format!("{}-{}-{}", name, now.as_secs(), now.subsec_nanos()))
+}
@@ -2,11 +2,33 @@ extern crate hyper;
 extern crate rustc_serialize;
 
 mod game;
+mod journal;
 mod server;
 mod types;
 
 use server::{run_server};
+use types::GameConfig;
+use std::env;
+use std::path::Path;
+use std::str::FromStr;
 
 fn main() {
-    run_server()
+    let args: Vec<String> = env::args().skip(1).collect();
+    let player_count = arg_value(&args, "--players").unwrap_or(4);
+    let seed = arg_value(&args, "--seed");
+    let edition: String = arg_value(&args, "--edition").unwrap_or("classic".to_string());
+    let data_dir: String = arg_value(&args, "--data-dir").unwrap_or("games".to_string());
+    let verify = args.iter().any(|a| a == "--verify");
+    let admin_token = arg_value(&args, "--admin-token");
+    match GameConfig::edition(&edition) {
+        Ok(config) => run_server(config, player_count, seed, Path::new(&data_dir), verify, admin_token),
+        Err(reason) => println!("{}", reason)
+    }
+}
+
+fn arg_value<T: FromStr>(args: &Vec<String>, name: &str) -> Option<T> {
+    args.iter()
+        .position(|a| a == name)
+        .and_then(|i| args.get(i + 1))
+        .and_then(|value| value.parse::<T>().ok())
 }
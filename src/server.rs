@@ -1,52 +1,563 @@
 extern crate hyper;
+extern crate rand;
 extern crate rustc_serialize;
 
 use game;
-use types::{Action, Game, Tile};
+use journal;
+use journal::Journal;
+use types::{Action, Game, GameConfig, GameError, Hotel, PlayerId, Tile, TurnState};
 
-use std::sync::Mutex;
+use std::collections::BTreeMap;
+use std::sync::{Condvar, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+use std::io;
 use std::io::Read;
+use std::path::{Path, PathBuf};
 use rustc_serialize::json;
+use rustc_serialize::json::Json;
 use rustc_serialize::Encodable;
 use self::hyper::{Get, Post};
-use self::hyper::header::ContentLength;
 use self::hyper::method::Method;
+use self::hyper::status::StatusCode;
 use self::hyper::server::{Handler, Request, Response, Server};
 use self::hyper::uri::RequestUri::AbsolutePath;
+use self::rand::{OsRng, Rng};
 
+/// An action sent to POST /action. `action` names the kind of action and decides which of the
+/// optional fields are needed; `token` is the secret handed out when `player` joined.
 #[derive(RustcDecodable, RustcEncodable, Debug)]
-pub struct PlaceTileCmd {
-    pub tile: Tile
+pub struct ActionCmd {
+    pub action: String,
+    pub player: PlayerId,
+    pub token: String,
+    pub tile: Option<Tile>,
+    pub hotel: Option<Hotel>,
+    pub hotels: Option<Vec<Hotel>>,
+    pub hold: Option<u8>,
+    pub sell: Option<u8>,
+    pub trade: Option<u8>,
+    pub ready: Option<bool>
+}
+
+impl ActionCmd {
+    pub fn new(action: &str, player: PlayerId, token: &str) -> ActionCmd {
+        ActionCmd {
+            action: action.to_string(),
+            player: player,
+            token: token.to_string(),
+            tile: None,
+            hotel: None,
+            hotels: None,
+            hold: None,
+            sell: None,
+            trade: None,
+            ready: None
+        }
+    }
+}
+
+pub type GameId = u32;
+
+/// How many actions a game goes between snapshots.
+const SNAPSHOT_INTERVAL: usize = 25;
+/// How long GET /events waits for a new action before answering with none.
+const LONG_POLL_SECONDS: u64 = 30;
+/// Requests handled at once. Waiting GET /events requests each hold one.
+const THREADS: usize = 32;
+/// How many GET /events requests may wait at once. The other threads stay free for actions.
+const MAX_WAITING: usize = THREADS / 2;
+
+/// Options for POST /games. There are 4 players unless told otherwise, and `config` takes
+/// precedence over `edition`, which defaults to classic. Only an admin may pick the `seed`,
+/// since whoever knows it knows the order of the tile bag.
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct NewGameCmd {
+    pub players: Option<u8>,
+    pub seed: Option<u64>,
+    pub edition: Option<String>,
+    pub config: Option<GameConfig>
+}
+
+/// A game as listed by GET /games. A game is open while it has free seats and is waiting for players.
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct GameSummary {
+    pub id: GameId,
+    pub seats: u8,
+    /// Names of the seated players.
+    pub players: Vec<String>,
+    pub free_seats: u8,
+    pub turn_state: TurnState
+}
+
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct JoinCmd {
+    pub name: String
+}
+
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct JoinResponse {
+    pub player: PlayerId,
+    pub token: String
+}
+
+/// The answer of GET /admin/verify: whether replaying every action from the start gives the game
+/// the server is playing on.
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct Verification {
+    pub actions: usize,
+    pub matches: bool
+}
+
+/// An accepted action as told by GET /events. `seq` counts actions from 1, `kind` is the name
+/// POST /action knows it by, and `action` is None when it draws a tile for another player.
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct GameEvent {
+    pub seq: usize,
+    pub player: PlayerId,
+    pub kind: String,
+    pub action: Option<Action>
+}
+
+/// The answer of GET /events. Ask for the next events with `since` set to `next`.
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct EventBatch {
+    pub events: Vec<GameEvent>,
+    pub next: usize,
+    pub turn: PlayerId,
+    pub turn_state: TurnState
+}
+
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct ErrorResponse {
+    pub code: String,
+    pub message: String
+}
+
+/// What each shareholder would receive if `hotel` were merged away right now.
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct BonusPayout {
+    pub hotel: Hotel,
+    pub payouts: Vec<Payout>
+}
+
+#[derive(RustcDecodable, RustcEncodable, Debug)]
+pub struct Payout {
+    pub player: PlayerId,
+    pub amount: i32
+}
+
+/// A taken seat and the secret of the player in it.
+struct Seat {
+    player: PlayerId,
+    token: String
+}
+
+/// The status and JSON body of an answer, worked out before it is sent.
+struct Reply {
+    status: StatusCode,
+    body: String
+}
+
+/// Why a request was turned away before it reached the game.
+enum Refusal {
+    BadRequest(String),
+    InvalidToken,
+    WrongSeat(PlayerId),
+    UnknownGame(GameId),
+    Rejected(GameError),
+    /// The action was valid but could not be written to the game's journal, so it was not played.
+    Unrecorded(String),
+    /// An admin route asked for without the server's admin token.
+    NotAdmin,
+    /// Too many GET /events requests are already waiting.
+    Busy
+}
+
+/// One game on the server: how it started, the actions played since and who sits where.
+/// Every action is written to the journal before it is added to `actions`.
+struct Table {
+    initial_game: Game,
+    actions: Vec<Action>,
+    /// The game after every action in `actions`, so requests need not replay them.
+    game: Game,
+    seats: Vec<Seat>,
+    journal: Journal
+}
+
+struct Registry {
+    next_id: GameId,
+    tables: BTreeMap<GameId, Table>,
+    /// Where the journals of the games are kept.
+    data_dir: PathBuf,
+    /// GET /events requests waiting for a new action right now.
+    waiting: usize
 }
 
 struct GameHandler {
-    actions: Mutex<Vec<Action>>,
-    initial_game: Game
+    registry: Mutex<Registry>,
+    /// Signalled after every POST so that waiting GET /events requests look for new actions.
+    changed: Condvar,
+    /// The secret the admin routes ask for. Without one they are turned off.
+    admin_token: Option<String>
 }
 
 impl Handler for GameHandler {
-    fn handle(&self, req: Request, mut res: Response) {
-        let mut actions = self.actions.lock().unwrap();
-        let game = game::compute_state(&self.initial_game, &actions).unwrap();
-        let (method, path, body) = parse_request(req);
+    fn handle(&self, req: Request, res: Response) {
+        let (method, uri, body) = parse_request(req);
+        let (path, query) = split_query(&uri);
+        // The query carries tokens, so only the path is logged.
         println!("{} {}", method, path);
-        match (method, path.as_ref()) {
-            (Get, "/state") => send_json(&game, res),
-            (Post, "/action") => {
-                match handle_action(&game, body) {
-                    Ok((game_after, action)) => {
-                        actions.push(action);
-                        send_json(&game_after, res)
-                    },
-                    Err(e) => send_error(e, res)
+        let posted = method == Post;
+        let reply = self.reply(method, path, query, body);
+        if posted {
+            self.changed.notify_all();
+        }
+        send(reply, res)
+    }
+}
+
+impl GameHandler {
+    /// Works out the answer to a request while holding the registry, which is released before
+    /// the answer is written so that a slow client holds up no other game.
+    fn reply(&self, method: Method, path: &str, query: &str, body: String) -> Reply {
+        let mut registry = self.registry.lock().unwrap();
+        if let (&Get, Some((id, "/events"))) = (&method, game_route(path)) {
+            return self.events(registry, id, query)
+        }
+        match (method, path) {
+            (Get, "/games") => json_reply(&list_games(&registry)),
+            (Post, "/games") => {
+                match create_game(&mut registry, body, self.is_admin(query)) {
+                    Ok(summary) => json_reply(&summary),
+                    Err(refusal) => refusal_reply(&refusal)
                 }
             }
-            _ => {
-                *res.status_mut() = hyper::BadRequest;
-                (*res.headers_mut()).set(ContentLength(0));
+            (method, path) => {
+                match game_route(path) {
+                    Some((id, route)) => {
+                        let admin = self.is_admin(query);
+                        match registry.tables.get_mut(&id) {
+                            Some(table) => handle_table(table, method, route, query, body, admin),
+                            None => refusal_reply(&Refusal::UnknownGame(id))
+                        }
+                    }
+                    None => not_found()
+                }
+            }
+        }
+    }
+
+    /// Whether the query holds `admin_token=...` matching the one the server was started with.
+    fn is_admin(&self, query: &str) -> bool {
+        match (self.admin_token.as_ref(), query_param(query, "admin_token")) {
+            (Some(secret), Some(given)) => secret == given,
+            _ => false
+        }
+    }
+
+    /// Answers GET /events?since=<n> with the actions after the first `n`, waiting up to
+    /// LONG_POLL_SECONDS when there are none yet. Without `since` it answers at once with no
+    /// events, only the cursor to start from. A `since` past the last action is refused, and so
+    /// is waiting once MAX_WAITING requests already are.
+    fn events(&self, mut registry: MutexGuard<Registry>, id: GameId, query: &str) -> Reply {
+        let since = match query_param(query, "since").map(|s| s.parse::<usize>()) {
+            None => None,
+            Some(Ok(since)) => Some(since),
+            Some(Err(_)) => return refusal_reply(&Refusal::BadRequest("'since' must be a number".to_string()))
+        };
+        let (viewer, next) = match registry.tables.get(&id).map(|table| (query_viewer(&table.seats, query), table.actions.len())) {
+            Some((Ok(viewer), next)) => (viewer, next),
+            Some((Err(refusal), _)) => return refusal_reply(&refusal),
+            None => return refusal_reply(&Refusal::UnknownGame(id))
+        };
+        if since.map_or(false, |since| since > next) {
+            return refusal_reply(&Refusal::BadRequest(format!("'since' is past the last action, {}", next)))
+        }
+        if since == Some(next) {
+            if registry.waiting >= MAX_WAITING {
+                return refusal_reply(&Refusal::Busy)
+            }
+            registry.waiting += 1;
+            let deadline = Instant::now() + Duration::from_secs(LONG_POLL_SECONDS);
+            loop {
+                let now = Instant::now();
+                let waiting = registry.tables.get(&id).map_or(false, |table| table.actions.len() == next);
+                if !waiting || now >= deadline {
+                    break
+                }
+                registry = self.changed.wait_timeout(registry, deadline - now).unwrap().0;
+            }
+            registry.waiting -= 1;
+        }
+        match registry.tables.get(&id) {
+            Some(table) => json_reply(&event_batch(table, since, viewer)),
+            None => refusal_reply(&Refusal::UnknownGame(id))
+        }
+    }
+}
+
+fn handle_table(table: &mut Table, method: Method, route: &str, query: &str, body: String, admin: bool) -> Reply {
+    match (method, route) {
+        (Get, "/state") => {
+            match query_viewer(&table.seats, query) {
+                Ok(viewer) => json_reply(&game::game_view(&table.game, viewer)),
+                Err(refusal) => refusal_reply(&refusal)
+            }
+        }
+        (Post, "/join") => {
+            match join(table, body) {
+                Ok(joined) => json_reply(&joined),
+                Err(refusal) => refusal_reply(&refusal)
+            }
+        }
+        (Post, "/leave") => act(table, with_action_kind(body, "leave")),
+        (Post, "/ready") => act(table, with_action_kind(body, "ready")),
+        (Post, "/start") => act(table, with_action_kind(body, "start_game")),
+        (Get, "/admin/state") if admin => json_reply(&table.game),
+        (Get, "/admin/state") => refusal_reply(&Refusal::NotAdmin),
+        (Get, "/admin/verify") if admin => json_reply(&verify_replay(table)),
+        (Get, "/admin/verify") => refusal_reply(&Refusal::NotAdmin),
+        (Get, "/chains") => json_reply(&game::chain_sizes(&table.game)),
+        (Get, "/bonuses") => json_reply(&bonus_payouts(&table.game)),
+        (Post, "/action") => act(table, Ok(body)),
+        _ => not_found()
+    }
+}
+
+fn act(table: &mut Table, body: Result<String, Refusal>) -> Reply {
+    let action = body.and_then(|body| parse_action(&table.game, &table.seats, body));
+    match action {
+        Ok(action) => {
+            match game::play_turn(&table.game, &action) {
+                Ok(game_after) => {
+                    let player = game::action_player(&action);
+                    match record(table, action, None, game_after) {
+                        Ok(()) => json_reply(&game::game_view(&table.game, Some(player))),
+                        Err(refusal) => refusal_reply(&refusal)
+                    }
+                },
+                Err(e) => error_reply(&e)
+            }
+        },
+        Err(refusal) => refusal_reply(&refusal)
+    }
+}
+
+/// Writes an accepted action to the journal, then plays it on the table. `game_after` is the game
+/// once the action is played. Every `SNAPSHOT_INTERVAL` actions the game is also snapshotted.
+fn record(table: &mut Table, action: Action, token: Option<String>, game_after: Game) -> Result<(), Refusal> {
+    if let Err(e) = table.journal.append(&action, token.as_ref().map(|t| &t[..])) {
+        return Err(Refusal::Unrecorded(e.to_string()))
+    }
+    update_seats(&mut table.seats, &action, token);
+    table.actions.push(action);
+    table.game = game_after;
+    if table.actions.len() % SNAPSHOT_INTERVAL == 0 {
+        if let Err(e) = table.journal.snapshot(&table.game) {
+            println!("Could not snapshot game: {}", e);
+        }
+    }
+    Ok(())
+}
+
+fn event_batch(table: &Table, since: Option<usize>, viewer: Option<PlayerId>) -> EventBatch {
+    let events = match since {
+        Some(since) => {
+            table.actions
+                .iter()
+                .enumerate()
+                .skip(since)
+                .map(|(i, action)| GameEvent {
+                    seq: i + 1,
+                    player: game::action_player(action),
+                    kind: action_kind(action).to_string(),
+                    action: game::action_view(action, viewer)
+                })
+                .collect()
+        }
+        None => Vec::new()
+    };
+    EventBatch {
+        events: events,
+        next: table.actions.len(),
+        turn: table.game.turn,
+        turn_state: table.game.turn_state.clone()
+    }
+}
+
+/// The name of an action in POST /action, or the route that takes it.
+fn action_kind(action: &Action) -> &'static str {
+    match *action {
+        Action::Join { .. } => "join",
+        Action::Leave { .. } => "leave",
+        Action::Ready { .. } => "ready",
+        Action::StartGame { .. } => "start_game",
+        Action::PlaceTile { .. } => "place_tile",
+        Action::PassPlacement { .. } => "pass_placement",
+        Action::ChooseSurvivor { .. } => "choose_survivor",
+        Action::ChooseDefunct { .. } => "choose_defunct",
+        Action::HandleMergeStocks { .. } => "handle_merge_stocks",
+        Action::BuyStocks { .. } => "buy_stocks",
+        Action::FoundChain { .. } => "found_chain",
+        Action::DrawTile { .. } => "draw_tile",
+        Action::EndTurn { .. } => "end_turn",
+        Action::ReplaceDeadTile { .. } => "replace_dead_tile",
+        Action::EndGame { .. } => "end_game"
+    }
+}
+
+/// Replays every action from the start and compares the result with the game being played.
+fn verify_replay(table: &Table) -> Verification {
+    let replayed = game::compute_state(&table.initial_game, &table.actions);
+    Verification { actions: table.actions.len(), matches: replayed.ok().map_or(false, |game| game == table.game) }
+}
+
+/// Gives a joining player their seat and frees the seat of a leaving one.
+fn update_seats(seats: &mut Vec<Seat>, action: &Action, token: Option<String>) {
+    match (action, token) {
+        (&Action::Join { player, .. }, Some(token)) => seats.push(Seat { player: player, token: token }),
+        (&Action::Leave { player }, _) => seats.retain(|s| s.player != player),
+        _ => {}
+    }
+}
+
+/// Lets POST /leave, /ready and /start take an action body without its `action` field.
+fn with_action_kind(body: String, kind: &str) -> Result<String, Refusal> {
+    match Json::from_str(&body) {
+        Ok(Json::Object(mut fields)) => {
+            fields.insert("action".to_string(), Json::String(kind.to_string()));
+            Ok(Json::Object(fields).to_string())
+        }
+        _ => Err(Refusal::BadRequest("The body must be a JSON object".to_string()))
+    }
+}
+
+/// Splits `/games/{id}/rest` into the game id and `/rest`.
+fn game_route(path: &str) -> Option<(GameId, &str)> {
+    if !path.starts_with("/games/") {
+        return None
+    }
+    let rest = &path["/games/".len()..];
+    let (id, route) = match rest.find('/') {
+        Some(i) => (&rest[..i], &rest[i..]),
+        None => (rest, "")
+    };
+    id.parse::<GameId>().ok().map(|id| (id, route))
+}
+
+fn list_games(registry: &Registry) -> Vec<GameSummary> {
+    registry.tables
+        .iter()
+        .map(|(&id, table)| summarize(id, table))
+        .collect()
+}
+
+fn summarize(id: GameId, table: &Table) -> GameSummary {
+    let game = &table.game;
+    GameSummary {
+        id: id,
+        seats: game.seats,
+        players: game.players.iter().map(|p| p.name.clone()).collect(),
+        free_seats: game.seats - game.players.len() as u8,
+        turn_state: game.turn_state.clone()
+    }
+}
+
+fn create_game(registry: &mut Registry, json: String, admin: bool) -> Result<GameSummary, Refusal> {
+    let cmd: NewGameCmd = match json::decode(&json) {
+        Ok(cmd) => cmd,
+        Err(e) => return Err(Refusal::BadRequest(e.to_string()))
+    };
+    let config = match (cmd.config, cmd.edition) {
+        (Some(config), _) => config,
+        (None, None) => GameConfig::classic(),
+        (None, Some(edition)) => match GameConfig::edition(&edition) {
+            Ok(config) => config,
+            Err(reason) => return Err(Refusal::BadRequest(reason))
+        }
+    };
+    let seats = cmd.players.unwrap_or(4);
+    let seed = match cmd.seed {
+        Some(_) if !admin => return Err(Refusal::NotAdmin),
+        Some(seed) => seed,
+        None => rand::random()
+    };
+    match game::new_lobby(config, seats, seed) {
+        Ok(initial_game) => add_table(registry, initial_game).map_err(|e| Refusal::Unrecorded(e.to_string())),
+        Err(e) => Err(Refusal::Rejected(e))
+    }
+}
+
+fn add_table(registry: &mut Registry, initial_game: Game) -> io::Result<GameSummary> {
+    let id = registry.next_id;
+    let journal = try!(Journal::create(&registry.data_dir, id, &initial_game));
+    let table = Table {
+        game: initial_game.clone(),
+        initial_game: initial_game,
+        actions: game::new_actions(),
+        seats: Vec::new(),
+        journal: journal
+    };
+    let summary = summarize(id, &table);
+    println!("Game {} seed: {}", id, table.initial_game.seed);
+    registry.tables.insert(id, table);
+    registry.next_id += 1;
+    Ok(summary)
+}
+
+/// Reloads every journal in `data_dir`, replaying only the actions after each game's snapshot
+/// unless `verify` asks for a full replay. Games whose journal is corrupt are reported and left
+/// out, but their ids are not reused so the files are never overwritten.
+fn load_registry(data_dir: &Path, verify: bool) -> Registry {
+    let mut registry = Registry { next_id: 1, tables: BTreeMap::new(), data_dir: data_dir.to_path_buf(), waiting: 0 };
+    let ids = match journal::journal_ids(data_dir) {
+        Ok(ids) => ids,
+        Err(e) => {
+            println!("Could not read {}: {}", data_dir.display(), e);
+            Vec::new()
+        }
+    };
+    for id in ids {
+        registry.next_id = id + 1;
+        match journal::load(data_dir, id, verify) {
+            Ok((recorded, journal)) => {
+                let mut table = Table {
+                    initial_game: recorded.initial_game,
+                    actions: game::new_actions(),
+                    game: recorded.game,
+                    seats: Vec::new(),
+                    journal: journal
+                };
+                for (action, token) in recorded.actions {
+                    update_seats(&mut table.seats, &action, token);
+                    table.actions.push(action);
+                }
+                if let Some(line) = recorded.cut_short {
+                    println!("Game {} journal line {} was cut short and has been dropped", id, line);
+                }
+                println!("Game {} restored with {} actions, {} replayed, seed: {}",
+                         id, table.actions.len(), recorded.replayed, table.initial_game.seed);
+                registry.tables.insert(id, table);
             }
+            Err(e) => println!("Game {} not restored, its journal is damaged: {}", id, e)
         }
     }
+    registry
+}
+
+fn bonus_payouts(game: &Game) -> Vec<BonusPayout> {
+    let sizes = game::chain_sizes(game);
+    game::all_hotels()
+        .into_iter()
+        .filter(|h| sizes.contains_key(h))
+        .map(|h| {
+            let payouts = game::bonuses(game, h.clone())
+                .into_iter()
+                .map(|(player, amount)| Payout { player: player, amount: amount })
+                .collect();
+            BonusPayout { hotel: h, payouts: payouts }
+        })
+        .collect()
 }
 
 fn parse_request(mut req: Request) -> (Method, String, String) {
@@ -59,38 +570,189 @@ fn parse_request(mut req: Request) -> (Method, String, String) {
     (req.method, path.clone(), body)
 }
 
-fn send_json<T: Encodable>(object: &T, res: Response) {
-    let encoded = json::encode(object).unwrap();
-    match res.send(encoded.as_bytes()) {
-        Ok(_) => {},
-        Err(e) => { println!("Error sending: {}", e) }
+fn split_query(uri: &str) -> (&str, &str) {
+    match uri.find('?') {
+        Some(i) => (&uri[..i], &uri[i + 1..]),
+        None => (uri, "")
+    }
+}
+
+fn query_param<'a>(query: &'a str, name: &str) -> Option<&'a str> {
+    query.split('&')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            match (parts.next(), parts.next()) {
+                (Some(key), Some(value)) if key == name => Some(value),
+                _ => None
+            }
+        })
+        .next()
+}
+
+/// The seat holding `?token=...`, or a spectator when the query has no token.
+fn query_viewer(seats: &Vec<Seat>, query: &str) -> Result<Option<PlayerId>, Refusal> {
+    match query_param(query, "token") {
+        None => Ok(None),
+        Some(token) => seat_of(seats, token).map(Some).ok_or(Refusal::InvalidToken)
     }
 }
 
-fn send_error(error_msg: String, res: Response) {
-    match res.send(error_msg.as_bytes()) {
+fn seat_of(seats: &Vec<Seat>, token: &str) -> Option<PlayerId> {
+    seats.iter()
+        .find(|s| s.token == token)
+        .map(|s| s.player)
+}
+
+/// Seats the player in the first free seat and hands them a new secret token for it.
+fn join(table: &mut Table, json: String) -> Result<JoinResponse, Refusal> {
+    let cmd: JoinCmd = match json::decode(&json) {
+        Ok(cmd) => cmd,
+        Err(e) => return Err(Refusal::BadRequest(e.to_string()))
+    };
+    let action = match game::join_action(&table.game, &cmd.name) {
+        Ok(action) => action,
+        Err(e) => return Err(Refusal::Rejected(e))
+    };
+    let game_after = match game::play_turn(&table.game, &action) {
+        Ok(game) => game,
+        Err(e) => return Err(Refusal::Rejected(e))
+    };
+    let player = game::action_player(&action);
+    let token = new_token();
+    record(table, action, Some(token.clone()), game_after).map(|_| JoinResponse { player: player, token: token })
+}
+
+fn new_token() -> String {
+    let mut rng = OsRng::new().unwrap();
+    rng.gen_ascii_chars().take(32).collect()
+}
+
+fn json_reply<T: Encodable>(object: &T) -> Reply {
+    Reply { status: StatusCode::Ok, body: json::encode(object).unwrap() }
+}
+
+fn error_reply(error: &GameError) -> Reply {
+    let response = ErrorResponse { code: error.code().to_string(), message: error.to_string() };
+    Reply { status: error_status(error), ..json_reply(&response) }
+}
+
+fn refusal_reply(refusal: &Refusal) -> Reply {
+    let (status, code, message) = match *refusal {
+        Refusal::Rejected(ref error) => return error_reply(error),
+        Refusal::BadRequest(ref error_msg) =>
+            (StatusCode::BadRequest, "invalid_command", error_msg.clone()),
+        Refusal::InvalidToken =>
+            (StatusCode::Unauthorized, "invalid_token", "The token does not belong to any seat".to_string()),
+        Refusal::WrongSeat(player) =>
+            (StatusCode::Forbidden, "wrong_seat", format!("The token does not belong to player {}", player)),
+        Refusal::UnknownGame(id) =>
+            (StatusCode::NotFound, "unknown_game", format!("There is no game {}", id)),
+        Refusal::Unrecorded(ref error_msg) =>
+            (StatusCode::InternalServerError, "not_recorded", format!("The game could not be saved: {}", error_msg)),
+        Refusal::NotAdmin =>
+            (StatusCode::Forbidden, "not_admin", "The admin_token is missing or wrong".to_string()),
+        Refusal::Busy =>
+            (StatusCode::ServiceUnavailable, "busy", "Too many requests are waiting for events, try again later".to_string())
+    };
+    let response = ErrorResponse { code: code.to_string(), message: message };
+    Reply { status: status, ..json_reply(&response) }
+}
+
+fn not_found() -> Reply {
+    Reply { status: StatusCode::NotFound, body: String::new() }
+}
+
+fn send(reply: Reply, mut res: Response) {
+    *res.status_mut() = reply.status;
+    match res.send(reply.body.as_bytes()) {
         Ok(_) => {},
         Err(e) => { println!("Error sending: {}", e) }
     }
 }
 
-fn handle_action(game: &Game, body: String) -> Result<(Game, Action), String> {
-    match parse_action(&game, body) {
-        Ok(action) => game::play_turn(&game, &action).map(|game_after| (game_after, action)),
-        Err(e) => Err(e)
+fn error_status(error: &GameError) -> StatusCode {
+    match *error {
+        GameError::NotYourTurn(_) => StatusCode::Forbidden,
+        GameError::WrongPhase(_) => StatusCode::Conflict,
+        GameError::CannotEndGame => StatusCode::Conflict,
+        GameError::NoTilesLeft => StatusCode::Conflict,
+        GameError::MustDrawTile => StatusCode::Conflict,
+        GameError::GameFull => StatusCode::Conflict,
+        GameError::SeatTaken(_) => StatusCode::Conflict,
+        GameError::NotHost(_) => StatusCode::Forbidden,
+        GameError::NotEnoughPlayers(_) => StatusCode::Conflict,
+        GameError::PlayersNotReady => StatusCode::Conflict,
+        _ => StatusCode::UnprocessableEntity
+    }
+}
+
+fn parse_action(game: &Game, seats: &Vec<Seat>, json: String) -> Result<Action, Refusal> {
+    let cmd: ActionCmd = match json::decode(&json) {
+        Ok(cmd) => cmd,
+        Err(e) => return Err(Refusal::BadRequest(e.to_string()))
+    };
+    match seat_of(seats, &cmd.token) {
+        None => return Err(Refusal::InvalidToken),
+        Some(player) if player != cmd.player => return Err(Refusal::WrongSeat(cmd.player)),
+        Some(_) => {}
+    }
+    let player = cmd.player;
+    match cmd.action.as_ref() {
+        "place_tile" => required(cmd.tile, "tile").map(|tile| Action::PlaceTile { player: player, tile: tile }),
+        "pass_placement" => Ok(Action::PassPlacement { player: player }),
+        "choose_survivor" => required(cmd.hotel, "hotel").map(|hotel| Action::ChooseSurvivor { player: player, hotel: hotel }),
+        "choose_defunct" => required(cmd.hotel, "hotel").map(|hotel| Action::ChooseDefunct { player: player, hotel: hotel }),
+        "handle_merge_stocks" => Ok(Action::HandleMergeStocks {
+            player: player,
+            hold: cmd.hold.unwrap_or(0),
+            sell: cmd.sell.unwrap_or(0),
+            trade: cmd.trade.unwrap_or(0)
+        }),
+        "buy_stocks" => Ok(Action::BuyStocks { player: player, hotels: cmd.hotels.unwrap_or(Vec::new()) }),
+        "found_chain" => required(cmd.hotel, "hotel").map(|hotel| Action::FoundChain { player: player, hotel: hotel }),
+        "draw_tile" => game::draw_action(game, player).map_err(Refusal::Rejected),
+        "end_turn" => Ok(Action::EndTurn { player: player }),
+        "replace_dead_tile" => {
+            required(cmd.tile, "tile").and_then(|tile| {
+                game::replace_dead_tile_action(game, player, tile).map_err(Refusal::Rejected)
+            })
+        }
+        "end_game" => Ok(Action::EndGame { player: player }),
+        "leave" => Ok(Action::Leave { player: player }),
+        "ready" => Ok(Action::Ready { player: player, ready: cmd.ready.unwrap_or(true) }),
+        "start_game" => Ok(Action::StartGame { player: player }),
+        other => Err(Refusal::BadRequest(format!("'{}' is not an action", other)))
     }
 }
 
-fn parse_action(game_before: &Game, json: String) -> Result<Action, String> {
-    let cmd: Result<PlaceTileCmd, String> = json::decode(&json).map_err(|e| e.to_string());
-    cmd.map(|c| Action::PlaceTile { player: game_before.turn.clone(), tile: c.tile })
+fn required<T>(value: Option<T>, field: &str) -> Result<T, Refusal> {
+    value.ok_or(Refusal::BadRequest(format!("The action needs a {}", field)))
 }
 
-pub fn run_server() {
-    let actions = Mutex::new(game::new_actions());
-    let initial_game = game::new_game();
-    let handler = GameHandler { actions: actions, initial_game: initial_game};
+/// Starts the server with the games journaled in `data_dir`. When there are none, it starts one game
+/// waiting for `player_count` players. More can be added with POST /games. With `verify`, every
+/// journal is replayed in full and checked against its snapshot.
+pub fn run_server(config: GameConfig, player_count: u8, seed: Option<u64>, data_dir: &Path, verify: bool, admin_token: Option<String>) {
+    let mut registry = load_registry(data_dir, verify);
+    if registry.tables.is_empty() {
+        start_first_game(&mut registry, config, player_count, seed);
+    }
+    let handler = GameHandler { registry: Mutex::new(registry), changed: Condvar::new(), admin_token: admin_token };
     let server = Server::http("localhost:3001").unwrap();
     println!("Starting server on localhost:3001");
-    let _ = server.handle(handler);
+    let _ = server.handle_threads(handler, THREADS);
+}
+
+fn start_first_game(registry: &mut Registry, config: GameConfig, player_count: u8, seed: Option<u64>) {
+    let new_game = game::new_lobby(config, player_count, seed.unwrap_or(rand::random()));
+    let initial_game = match new_game {
+        Ok(game) => game,
+        Err(e) => {
+            println!("Could not start game: {}", e);
+            return
+        }
+    };
+    if let Err(e) = add_table(registry, initial_game) {
+        println!("Could not save game: {}", e)
+    }
 }
@@ -1,37 +1,152 @@
-pub const ROWS: u8 = 9;
-pub const COLS: u8 = 12;
-pub const TILES: u8 = 108;
-pub const PLAYERS: u8 = 4;
+use std::collections::HashMap;
+use std::fmt;
 
-#[derive(RustcDecodable, RustcEncodable, Clone)]
+/// Row labels run from A to Z, which bounds the number of rows.
+pub const MAX_ROWS: u8 = 26;
+pub const MIN_PLAYERS: u8 = 2;
+pub const MAX_PLAYERS: u8 = 6;
+/// Bounds on the money in a config, which keep prices, bonuses and cash far from overflowing.
+pub const MAX_STARTING_CASH: i32 = 1000000;
+pub const MAX_PRICE: i32 = 10000;
+pub const MAX_PRICE_LEVELS: usize = 12;
+pub const MAX_BONUS: i32 = 20;
+
+#[derive(RustcDecodable, RustcEncodable, Clone, PartialEq)]
 pub struct Game {
+    pub config: GameConfig,
+    pub seed: u64,
+    /// How many players the game has room for.
+    pub seats: u8,
+    /// The player who may start the game. None while nobody has joined.
+    pub host: Option<PlayerId>,
+    /// Seated players in turn order.
     pub players: Vec<Player>,
     pub board: Board,
+    /// Tiles left in the bag, drawn from the end.
+    pub draw_pile: Vec<Tile>,
+    pub bank_shares: PlayerShares,
+    /// The tile each player drew to decide who starts. These tiles begin the game on the board.
+    pub starting_draw: Vec<StartingTile>,
+    /// 0 until the game starts.
     pub turn: PlayerId,
-    pub turn_state: TurnState
+    pub turn_state: TurnState,
+    pub placed_tile: Option<Tile>,
+    /// Set when the player to place has no legal tile and may only pass.
+    pub blocked_placement: Option<BlockedPlacement>,
+    pub merger: Option<Merger>,
+    pub final_score: Option<FinalScore>
 }
 
-#[derive(RustcDecodable, RustcEncodable, Clone)]
+/// Rule values a table may change without forking the engine.
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+pub struct GameConfig {
+    pub rows: u8,
+    pub cols: u8,
+    pub starting_cash: i32,
+    pub hand_size: u8,
+    pub max_stock_purchase: u8,
+    pub shares_per_hotel: u8,
+    /// Share prices of a two-tile chain for Luxor and Tower, for American, Festival and
+    /// Worldwide, and for Continental and Imperial.
+    pub cheap_price: i32,
+    pub medium_price: i32,
+    pub expensive_price: i32,
+    /// Smallest chain size of each price level. Each level adds `price_step` to the share price.
+    pub price_levels: Vec<u8>,
+    pub price_step: i32,
+    pub safe_chain_size: u8,
+    pub end_chain_size: u8,
+    /// Majority and minority bonuses as multiples of the share price.
+    pub majority_bonus: i32,
+    pub minority_bonus: i32
+}
+
+impl GameConfig {
+    pub fn classic() -> GameConfig {
+        GameConfig {
+            rows: 9,
+            cols: 12,
+            starting_cash: 6000,
+            hand_size: 6,
+            max_stock_purchase: 3,
+            shares_per_hotel: 25,
+            cheap_price: 200,
+            medium_price: 300,
+            expensive_price: 400,
+            price_levels: vec![2, 3, 4, 5, 6, 11, 21, 31, 41],
+            price_step: 100,
+            safe_chain_size: 11,
+            end_chain_size: 41,
+            majority_bonus: 10,
+            minority_bonus: 5
+        }
+    }
+
+    /// The preset with the given name. Only `classic` exists so far. The 1999 and 2008 editions
+    /// are refused by name until their rules are encoded, rather than handed out as copies of
+    /// classic; until then their tables can be given as a full config.
+    pub fn edition(name: &str) -> Result<GameConfig, String> {
+        match name {
+            "classic" => Ok(GameConfig::classic()),
+            "1999" | "2008" =>
+                Err(format!("The {} edition is not available yet, use classic or give its rules as a config", name)),
+            _ => Err(format!("'{}' is not an edition, expected classic", name))
+        }
+    }
+}
+
+#[derive(RustcDecodable, RustcEncodable, Clone, PartialEq)]
 pub struct Player {
     pub id: PlayerId,
+    pub name: String,
+    /// Whether the player is ready for the host to start. Only used before the game starts.
+    pub ready: bool,
     pub money: i32,
     pub shares: PlayerShares,
     pub tiles: Vec<Tile>
 }
 
-#[derive(RustcDecodable, RustcEncodable, Debug, Clone, PartialEq)]
-pub enum PlayerId { One, Two, Three, Four }
+/// The game as one player may see it. The tile bag and the seed, which fixes its order, are
+/// left out, and so are the tiles in other players' hands.
+#[derive(RustcDecodable, RustcEncodable, Clone)]
+pub struct GameView {
+    /// None for spectators, who see no hand at all.
+    pub viewer: Option<PlayerId>,
+    pub config: GameConfig,
+    pub seats: u8,
+    pub host: Option<PlayerId>,
+    pub players: Vec<PlayerView>,
+    pub board: Board,
+    pub tiles_in_bag: usize,
+    pub bank_shares: PlayerShares,
+    pub starting_draw: Vec<StartingTile>,
+    pub turn: PlayerId,
+    pub turn_state: TurnState,
+    pub placed_tile: Option<Tile>,
+    pub blocked_placement: Option<BlockedPlacement>,
+    pub merger: Option<Merger>,
+    pub final_score: Option<FinalScore>
+}
 
-impl PlayerId {
-    pub fn new(id: u8) -> Option<PlayerId>{
-        match id {
-            1 => { Some(PlayerId::One) }
-            2 => { Some(PlayerId::Two) }
-            3 => { Some(PlayerId::Three) }
-            4 => { Some(PlayerId::Four) }
-            _ => { None }
-        }
-    }
+#[derive(RustcDecodable, RustcEncodable, Clone)]
+pub struct PlayerView {
+    pub id: PlayerId,
+    pub name: String,
+    pub ready: bool,
+    pub money: i32,
+    pub shares: PlayerShares,
+    pub hand_size: usize,
+    /// Empty unless this is the viewer.
+    pub tiles: Vec<Tile>
+}
+
+/// Seats are numbered from 1 in turn order.
+pub type PlayerId = u8;
+
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+pub struct StartingTile {
+    pub player: PlayerId,
+    pub tile: Tile
 }
 
 #[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
@@ -49,8 +164,8 @@ pub struct PlayerShares {
 pub struct Tile { pub row: u8, pub col: u8 }
 
 impl Tile {
-    pub fn new(row: u8, col: u8) -> Option<Tile> {
-        if row >= ROWS || col >= COLS {
+    pub fn new(row: u8, col: u8, config: &GameConfig) -> Option<Tile> {
+        if row >= config.rows || col >= config.cols {
             None
         } else {
             Some(Tile {row: row, col: col})
@@ -64,9 +179,147 @@ impl Tile {
     }
 }
 
-#[derive(RustcDecodable, RustcEncodable, Clone)]
+#[derive(RustcDecodable, RustcEncodable, Clone, PartialEq)]
 pub struct Board {
-    pub slots: Vec<Slot>
+    pub rows: u8,
+    pub cols: u8,
+    /// Row by row, starting from 1A, so the slot of a tile is at `row * cols + col`.
+    pub slots: Vec<Slot>,
+    /// The number of placed tiles of every chain on the board, kept up to date by `place` and `tag`.
+    pub chains: HashMap<Hotel, usize>
+}
+
+impl Board {
+    /// A board without any tiles.
+    pub fn new(rows: u8, cols: u8) -> Board {
+        let slots = (0..rows).flat_map(|row| -> Vec<Slot> {
+            (0..cols).map(|col| {
+                Slot { row: row, col: col, hotel: None, has_tile: false }
+            }).collect()
+        }).collect();
+        Board { rows: rows, cols: cols, slots: slots, chains: HashMap::new() }
+    }
+
+    fn index(&self, row: u8, col: u8) -> Option<usize> {
+        if row < self.rows && col < self.cols {
+            Some(row_major(&Tile { row: row, col: col }, self.cols))
+        } else {
+            None
+        }
+    }
+
+    pub fn slot(&self, tile: &Tile) -> Option<&Slot> {
+        self.index(tile.row, tile.col).map(|i| &self.slots[i])
+    }
+
+    /// Puts a tile on its slot.
+    pub fn place(&mut self, tile: &Tile) {
+        if let Some(i) = self.index(tile.row, tile.col) {
+            if !self.slots[i].has_tile {
+                self.slots[i].has_tile = true;
+                if let Some(hotel) = self.slots[i].hotel.clone() {
+                    *self.chains.entry(hotel).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    /// Makes the slots of `tiles` part of the chain of `hotel`, taking them from any chain they were in.
+    pub fn tag(&mut self, tiles: &Vec<Tile>, hotel: &Hotel) {
+        for tile in tiles.iter() {
+            if let Some(i) = self.index(tile.row, tile.col) {
+                let previous = self.slots[i].hotel.take();
+                self.slots[i].hotel = Some(hotel.clone());
+                if self.slots[i].has_tile {
+                    if let Some(previous) = previous {
+                        self.shrink(&previous);
+                    }
+                    *self.chains.entry(hotel.clone()).or_insert(0) += 1;
+                }
+            }
+        }
+    }
+
+    fn shrink(&mut self, hotel: &Hotel) {
+        let left = match self.chains.get_mut(hotel) {
+            Some(size) => {
+                *size -= 1;
+                *size
+            }
+            None => return
+        };
+        if left == 0 {
+            self.chains.remove(hotel);
+        }
+    }
+
+    pub fn has_tile(&self, tile: &Tile) -> bool {
+        self.slot(tile).map_or(false, |s| s.has_tile)
+    }
+
+    /// The positions above, left of, below and right of `tile` that lie on the board.
+    pub fn neighbors(&self, tile: &Tile) -> Vec<Tile> {
+        let mut neighbors = Vec::with_capacity(4);
+        if tile.row > 0 {
+            neighbors.push(Tile { row: tile.row - 1, col: tile.col });
+        }
+        if tile.col > 0 {
+            neighbors.push(Tile { row: tile.row, col: tile.col - 1 });
+        }
+        if tile.row + 1 < self.rows {
+            neighbors.push(Tile { row: tile.row + 1, col: tile.col });
+        }
+        if tile.col + 1 < self.cols {
+            neighbors.push(Tile { row: tile.row, col: tile.col + 1 });
+        }
+        neighbors
+    }
+
+    /// The slots next to `tile` that hold a tile.
+    pub fn placed_neighbors(&self, tile: &Tile) -> Vec<&Slot> {
+        self.neighbors(tile)
+            .iter()
+            .filter_map(|t| self.slot(t))
+            .filter(|s| s.has_tile)
+            .collect()
+    }
+
+    /// The number of placed tiles of every chain on the board.
+    pub fn chain_sizes(&self) -> HashMap<Hotel, usize> {
+        self.chains.clone()
+    }
+
+    /// The number of placed tiles in the chain of `hotel`, 0 when it is not on the board.
+    pub fn chain_size(&self, hotel: &Hotel) -> usize {
+        self.chains.get(hotel).cloned().unwrap_or(0)
+    }
+
+    /// All placed tiles reachable from `tile` through placed tiles, including `tile` itself.
+    pub fn connected_group(&self, tile: &Tile) -> Vec<Tile> {
+        let mut visited = vec![false; self.slots.len()];
+        let mut group: Vec<Tile> = Vec::new();
+        if let Some(i) = self.index(tile.row, tile.col) {
+            visited[i] = true;
+            group.push(tile.clone());
+        }
+        let mut next = 0;
+        while next < group.len() {
+            let current = group[next].clone();
+            for neighbor in self.neighbors(&current) {
+                let i = row_major(&neighbor, self.cols);
+                if !visited[i] && self.slots[i].has_tile {
+                    visited[i] = true;
+                    group.push(neighbor);
+                }
+            }
+            next += 1;
+        }
+        group
+    }
+}
+
+fn row_major(tile: &Tile, cols: u8) -> usize {
+    tile.row as usize * cols as usize + tile.col as usize
 }
 
 #[derive(RustcDecodable, RustcEncodable, Clone, PartialEq)]
@@ -77,23 +330,216 @@ pub struct Slot {
     pub hotel: Option<Hotel>
 }
 
-#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Hotel { Luxor, Tower, American, Festival, Worldwide, Continental, Imperial }
 
 #[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
 pub enum TurnState {
+    WaitingForPlayers,
     Placing,
     BuyingOrDrawing,
     Drawing,
     CreatingChain,
-    Merging
+    ChoosingSurvivor,
+    ChoosingDefunct,
+    Merging,
+    GameOver
+}
+
+/// Progress of a merger started by the current player's tile.
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+pub struct Merger {
+    /// Every chain touching the placed tile.
+    pub chains: Vec<Hotel>,
+    /// None while the mergemaker still has to pick between equally large chains.
+    pub survivor: Option<Hotel>,
+    /// Defunct chains not yet absorbed, largest first. The first one is being resolved. When
+    /// several of them are equally large the mergemaker picks which goes first.
+    pub defunct: Vec<Hotel>,
+    /// Shareholders of the current defunct chain still to decide, in turn order.
+    pub shareholders: Vec<PlayerId>
 }
 
-#[derive(Debug)]
+/// Players ranked by their money after final bonuses and share sales.
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+pub struct FinalScore {
+    pub standings: Vec<Standing>
+}
+
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+pub struct Standing {
+    pub rank: u8,
+    pub player: PlayerId,
+    pub money: i32
+}
+
+/// Why a tile cannot be placed. Tiles merging safe chains are dead for the rest of the
+/// game; tiles that would found an eighth chain may become playable again.
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+pub enum Unplayable {
+    MergesSafeChains,
+    NoChainAvailable
+}
+
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+pub struct BlockedPlacement {
+    pub player: PlayerId,
+    /// Every tile in the player's hand with the reason it cannot be placed. Empty when the hand is,
+    /// and in the views of everyone but the blocked player.
+    pub tiles: Vec<BlockedTile>
+}
+
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug, PartialEq)]
+pub struct BlockedTile {
+    pub tile: Tile,
+    pub reason: Unplayable
+}
+
+#[derive(RustcDecodable, RustcEncodable, Clone, Debug)]
 pub enum Action {
+    Join { player: PlayerId, name: String },
+    Leave { player: PlayerId },
+    Ready { player: PlayerId, ready: bool },
+    StartGame { player: PlayerId },
     PlaceTile { player: PlayerId, tile: Tile },
-    HandleMergeStocks { hold: u8, sell: u8, trade: u8 },
-    BuyStocks { player: PlayerId, hotel1: Option<Hotel>, hotel2: Option<Hotel>, hotel3: Option<Hotel> },
-    DrawTile,
-    EndGame
+    PassPlacement { player: PlayerId },
+    ChooseSurvivor { player: PlayerId, hotel: Hotel },
+    ChooseDefunct { player: PlayerId, hotel: Hotel },
+    HandleMergeStocks { player: PlayerId, hold: u8, sell: u8, trade: u8 },
+    BuyStocks { player: PlayerId, hotels: Vec<Hotel> },
+    FoundChain { player: PlayerId, hotel: Hotel },
+    DrawTile { player: PlayerId, tile: Tile },
+    /// Ends the turn without drawing, which is only allowed once the bag is empty.
+    EndTurn { player: PlayerId },
+    ReplaceDeadTile { player: PlayerId, tile: Tile, replacement: Tile },
+    EndGame { player: PlayerId }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameError {
+    NotYourTurn(PlayerId),
+    TileNotInHand(PlayerId, Tile),
+    WrongPhase(TurnState),
+    InsufficientFunds { cost: i32, money: i32 },
+    TooManyShares { max: u8 },
+    BankOutOfShares(Hotel),
+    IllegalTile(Tile, Unplayable),
+    TileNotDead(Tile),
+    MustPlaceTile,
+    HotelNotOnBoard(Hotel),
+    HotelAlreadyOnBoard(Hotel),
+    NotLargestChain(Hotel),
+    MergeSharesMismatch { owned: u8, decided: u32 },
+    OddTrade(u8),
+    InvalidPlayerCount(u8),
+    InvalidBoardSize { rows: u8, cols: u8 },
+    InvalidConfig(String),
+    UnknownPlayer(PlayerId),
+    GameFull,
+    SeatTaken(PlayerId),
+    NotHost(PlayerId),
+    NotEnoughPlayers(u8),
+    PlayersNotReady,
+    NoTilesLeft,
+    MustDrawTile,
+    TileNotInBag(Tile),
+    CannotEndGame
+}
+
+impl GameError {
+    /// Stable identifier for clients, independent of the message wording.
+    pub fn code(&self) -> &'static str {
+        match *self {
+            GameError::NotYourTurn(_) => "not_your_turn",
+            GameError::TileNotInHand(_, _) => "tile_not_in_hand",
+            GameError::WrongPhase(_) => "wrong_phase",
+            GameError::InsufficientFunds { .. } => "insufficient_funds",
+            GameError::TooManyShares { .. } => "too_many_shares",
+            GameError::BankOutOfShares(_) => "bank_out_of_shares",
+            GameError::IllegalTile(_, _) => "illegal_tile",
+            GameError::TileNotDead(_) => "tile_not_dead",
+            GameError::MustPlaceTile => "must_place_tile",
+            GameError::HotelNotOnBoard(_) => "hotel_not_on_board",
+            GameError::HotelAlreadyOnBoard(_) => "hotel_already_on_board",
+            GameError::NotLargestChain(_) => "not_largest_chain",
+            GameError::MergeSharesMismatch { .. } => "merge_shares_mismatch",
+            GameError::OddTrade(_) => "odd_trade",
+            GameError::InvalidPlayerCount(_) => "invalid_player_count",
+            GameError::InvalidBoardSize { .. } => "invalid_board_size",
+            GameError::InvalidConfig(_) => "invalid_config",
+            GameError::UnknownPlayer(_) => "unknown_player",
+            GameError::GameFull => "game_full",
+            GameError::SeatTaken(_) => "seat_taken",
+            GameError::NotHost(_) => "not_host",
+            GameError::NotEnoughPlayers(_) => "not_enough_players",
+            GameError::PlayersNotReady => "players_not_ready",
+            GameError::NoTilesLeft => "no_tiles_left",
+            GameError::MustDrawTile => "must_draw_tile",
+            GameError::TileNotInBag(_) => "tile_not_in_bag",
+            GameError::CannotEndGame => "cannot_end_game"
+        }
+    }
+}
+
+impl fmt::Display for GameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            GameError::NotYourTurn(player) =>
+                write!(f, "Player {} does not have turn", player),
+            GameError::TileNotInHand(player, ref tile) =>
+                write!(f, "Player {} does not have tile {:?}", player, tile),
+            GameError::WrongPhase(ref turn_state) =>
+                write!(f, "Action is not allowed while {:?}", turn_state),
+            GameError::InsufficientFunds { cost, money } =>
+                write!(f, "Cannot afford {} with {}", cost, money),
+            GameError::TooManyShares { max } =>
+                write!(f, "At most {} shares can be bought per turn", max),
+            GameError::BankOutOfShares(ref hotel) =>
+                write!(f, "The bank does not have enough shares of {:?} left", hotel),
+            GameError::IllegalTile(ref tile, Unplayable::MergesSafeChains) =>
+                write!(f, "Tile {:?} cannot be placed because it would merge safe chains", tile),
+            GameError::IllegalTile(ref tile, Unplayable::NoChainAvailable) =>
+                write!(f, "Tile {:?} cannot be placed because every chain is already on the board", tile),
+            GameError::TileNotDead(ref tile) =>
+                write!(f, "Tile {:?} can still be placed", tile),
+            GameError::MustPlaceTile =>
+                write!(f, "A tile can be placed, so placement cannot be passed"),
+            GameError::HotelNotOnBoard(ref hotel) =>
+                write!(f, "{:?} is not on the board", hotel),
+            GameError::HotelAlreadyOnBoard(ref hotel) =>
+                write!(f, "{:?} is already on the board", hotel),
+            GameError::NotLargestChain(ref hotel) =>
+                write!(f, "{:?} is not one of the largest merging chains", hotel),
+            GameError::MergeSharesMismatch { owned, decided } =>
+                write!(f, "Decided on {} shares but owns {}", decided, owned),
+            GameError::OddTrade(trade) =>
+                write!(f, "{} shares cannot be traded two for one", trade),
+            GameError::InvalidPlayerCount(count) =>
+                write!(f, "A game needs {} to {} players, not {}", MIN_PLAYERS, MAX_PLAYERS, count),
+            GameError::InvalidBoardSize { rows, cols } =>
+                write!(f, "A board of {} rows and {} columns cannot hold this game", rows, cols),
+            GameError::InvalidConfig(ref reason) =>
+                write!(f, "The config is out of range: {}", reason),
+            GameError::UnknownPlayer(player) =>
+                write!(f, "There is no player {} in this game", player),
+            GameError::GameFull =>
+                write!(f, "Every seat has been taken"),
+            GameError::SeatTaken(seat) =>
+                write!(f, "Seat {} has already been taken", seat),
+            GameError::NotHost(player) =>
+                write!(f, "Player {} is not the host", player),
+            GameError::NotEnoughPlayers(joined) =>
+                write!(f, "A game needs at least {} players, only {} have joined", MIN_PLAYERS, joined),
+            GameError::PlayersNotReady =>
+                write!(f, "Not every player is ready"),
+            GameError::NoTilesLeft =>
+                write!(f, "There are no tiles left to draw"),
+            GameError::MustDrawTile =>
+                write!(f, "There are tiles left in the bag, so the turn ends with a draw"),
+            GameError::TileNotInBag(ref tile) =>
+                write!(f, "Tile {:?} is not on top of the bag", tile),
+            GameError::CannotEndGame =>
+                write!(f, "The game cannot be ended yet")
+        }
+    }
 }